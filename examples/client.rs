@@ -0,0 +1,24 @@
+//! Prints completed orders from a running service, reconnecting as needed.
+//!
+//!     cargo run --example client --features client -- ws://localhost:3000
+
+use block_delivery_service::{
+    client::{connect, Filter},
+    EventKind,
+};
+use tokio_stream::StreamExt;
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt::init();
+    let url = std::env::args().nth(1).unwrap_or("ws://localhost:3000".to_string());
+    let filter = Filter {
+        kind: Some(EventKind::OrderCompleted),
+        ..Filter::default()
+    };
+    let mut events = std::pin::pin!(connect(&url, filter));
+
+    while let Some(event) = events.next().await {
+        println!("{:?}", event);
+    }
+}
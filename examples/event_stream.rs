@@ -0,0 +1,16 @@
+//! Prints every decoded event, without the HTTP/WS server.
+//!
+//!     PROGRAM_ID=... WS_URL=ws://127.0.0.1:8900 cargo run --example event_stream
+
+use block_delivery_service::{event_stream, Config};
+use tokio_stream::StreamExt;
+
+#[tokio::main]
+async fn main() {
+    let config = Config::from_env().expect("config");
+    let mut events = std::pin::pin!(event_stream(config));
+
+    while let Some(event) = events.next().await {
+        println!("{:?}", event);
+    }
+}
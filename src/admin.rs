@@ -0,0 +1,309 @@
+use crate::{
+    config::Config,
+    deadletter::DeadLetter,
+    events::{event_discriminator, EventScheme, OrderCreated},
+    listener::{self, ParseError},
+    log_style::icon,
+    orders::now_secs,
+    state::AppState,
+    WebEvent,
+};
+use anchor_lang::prelude::{borsh::to_vec, Pubkey};
+use base64::{engine::general_purpose, Engine as _};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use axum::{
+    extract::{Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use tracing::{info, warn};
+
+//
+// ---------------- Admin routes (`ADMIN_TOKEN`)
+//
+/// Operator routes, all behind `Authorization: Bearer $ADMIN_TOKEN`.
+pub fn router(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/admin/config", get(config_handler))
+        .route("/admin/pause", post(pause_handler))
+        .route("/admin/resume", post(resume_handler))
+        .route("/admin/selftest", post(selftest_handler))
+        .route("/admin/deadletter", get(dead_letter_handler))
+        .route("/admin/stats/reset", post(stats_reset_handler))
+        .route("/admin/resubscribe", post(resubscribe_handler))
+        .route("/admin/promote", post(promote_handler))
+        .route("/admin/decode", post(admin_decode_handler))
+        .route("/debug/decode", post(decode_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+}
+
+async fn require_token(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    match &state.config.admin_token {
+        Some(expected) if bearer_matches(req.headers(), expected) => next.run(req).await,
+        _ => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+/// Whether the request carries `Authorization: Bearer <expected>`.
+pub fn bearer_matches(headers: &HeaderMap, expected: &str) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|token| constant_time_eq(token, expected))
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// The resolved configuration, with credentials masked.
+async fn config_handler(State(state): State<AppState>) -> Json<Config> {
+    Json(state.config.as_ref().clone())
+}
+
+/// Recent payloads that matched a discriminator but failed to deserialize.
+async fn dead_letter_handler(State(state): State<AppState>) -> Json<Vec<DeadLetter>> {
+    Json(state.dead_letters.list())
+}
+
+/// Zeroes the `/metrics` and `/stats/programs` counters (reconnect count and
+/// `last_seen_at` included), the courier leaderboard and the open `Tick`
+/// window, so a load test can read deltas. The order-state projection and the
+/// replay buffer are untouched: clients still need them to catch up.
+async fn stats_reset_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
+    state.metrics.reset();
+    for (_, counters) in state.program_stats.iter() {
+        counters.reset();
+    }
+    state.leaderboard.reset();
+    state.ticker.reset();
+    let token = state.config.admin_token.as_deref().unwrap_or_default();
+    let reset_at = now_secs();
+    warn!(
+        "{} Stats counters reset by token {} at {}",
+        icon("🧹"),
+        token_id(token),
+        reset_at
+    );
+    Json(serde_json::json!({ "reset_at": reset_at }))
+}
+
+/// Makes every listener drop its subscription and subscribe again right
+/// away, skipping any backoff wait.
+async fn resubscribe_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let listeners = state.resubscribe.trigger();
+    let token = state.config.admin_token.as_deref().unwrap_or_default();
+    warn!(
+        "{} Resubscribe of {} listeners requested by token {}",
+        icon("🔁"),
+        listeners,
+        token_id(token)
+    );
+    Json(serde_json::json!({ "listeners": listeners }))
+}
+
+/// Turns a standby into a primary: broadcasting starts with the next event
+/// and WebSocket clients are accepted. Idempotent.
+async fn promote_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let promoted = state.promote();
+    if promoted {
+        let token = state.config.admin_token.as_deref().unwrap_or_default();
+        warn!(
+            "{} Promoted to primary by token {} at seq {}",
+            icon("👑"),
+            token_id(token),
+            state.replay.last_seq()
+        );
+    }
+    Json(serde_json::json!({ "role": "primary", "promoted": promoted }))
+}
+
+/// Short fingerprint naming a token in logs without revealing it.
+fn token_id(token: &str) -> String {
+    Sha256::digest(token.as_bytes())[..4]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Withholds broadcasts (events are still decoded, buffered and projected)
+/// until `/admin/resume`.
+async fn pause_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
+    if state.pause.pause() {
+        warn!("{} Broadcasting paused", icon("⏸️"));
+    }
+    Json(serde_json::json!({ "paused": true }))
+}
+
+async fn resume_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let flushed = state.pause.resume(|item| state.broadcast(item));
+    if let Some(flushed) = flushed {
+        info!("{} Broadcasting resumed, flushed {} held events", icon("▶️"), flushed);
+    }
+    Json(serde_json::json!({ "paused": false, "flushed": flushed.unwrap_or(0) }))
+}
+
+#[derive(Debug, Deserialize)]
+struct DecodeQuery {
+    /// Decode with this program's `PROGRAM_EVENT_SCHEME` instead of Anchor's.
+    program_id: Option<String>,
+}
+
+/// The payload as the raw request body.
+async fn decode_handler(
+    State(state): State<AppState>,
+    Query(query): Query<DecodeQuery>,
+    body: String,
+) -> Response {
+    decode(&state, &body, query.program_id.as_deref())
+}
+
+#[derive(Debug, Deserialize)]
+struct DecodeRequest {
+    data: String,
+    program_id: Option<String>,
+}
+
+/// The payload as `{"data":"…"}`.
+async fn admin_decode_handler(
+    State(state): State<AppState>,
+    Json(request): Json<DecodeRequest>,
+) -> Response {
+    decode(&state, &request.data, request.program_id.as_deref())
+}
+
+/// Runs a `Program data:` payload (prefix optional) through the same decoder
+/// as the listener and reports the event, or why it didn't decode.
+fn decode(state: &AppState, data: &str, program_id: Option<&str>) -> Response {
+    let data = data.trim();
+    let line = if listener::strip_data_prefix(data, &state.config).is_some() {
+        data.to_string()
+    } else {
+        format!("{}{}", state.config.data_prefix, data)
+    };
+
+    let scheme = program_id
+        .and_then(|id| id.parse().ok())
+        .map_or(EventScheme::anchor(), |id| state.config.event_scheme(&id));
+    match listener::parse_log(&line, &state.config, scheme) {
+        Ok((event, _)) => Json(serde_json::json!({ "matched": true, "event": event })).into_response(),
+        Err(e) => (StatusCode::UNPROCESSABLE_ENTITY, Json(decode_error(&e))).into_response(),
+    }
+}
+
+/// `error` is meant for people; `reason` and its fields carry the same for
+/// programs.
+fn decode_error(e: &ParseError) -> serde_json::Value {
+    let mut body = match e {
+        ParseError::NotEventData => serde_json::json!({ "reason": "not_event_data" }),
+        ParseError::Oversized(bytes) => serde_json::json!({ "reason": "oversized", "bytes": bytes }),
+        ParseError::InvalidBase64 => serde_json::json!({ "reason": "invalid_base64" }),
+        ParseError::TooShort(bytes) => serde_json::json!({ "reason": "too_short", "bytes": bytes }),
+        ParseError::Disabled(kind) => serde_json::json!({ "reason": "disabled", "event": kind }),
+        ParseError::UnknownDiscriminator(disc) => serde_json::json!({
+            "reason": "unknown_discriminator",
+            "discriminator": listener::hex(disc),
+        }),
+        ParseError::Borsh(kind, error) => serde_json::json!({
+            "reason": "borsh",
+            "event": kind,
+            "borsh_error": error,
+        }),
+    };
+    body["matched"] = false.into();
+    body["error"] = e.to_string().into();
+    body
+}
+
+/// Encodes a synthetic `OrderCreated` the way the program emits it and feeds
+/// it through `parse_log`; `ok: false` points at a discriminator or schema bug.
+async fn selftest_handler(State(state): State<AppState>) -> Response {
+    let created = OrderCreated {
+        order: Pubkey::new_unique(),
+        order_id: 42,
+        customer: Pubkey::new_unique(),
+        amount: 1_000,
+    };
+    let expected = WebEvent::OrderCreated {
+        order: created.order.to_string(),
+        order_id: created.order_id,
+        customer: created.customer.to_string(),
+        amount: created.amount,
+    };
+
+    let mut bytes = event_discriminator("OrderCreated").to_vec();
+    bytes.extend(to_vec(&created).expect("borsh serialization into a Vec"));
+    let line = format!(
+        "{}{}",
+        state.config.data_prefix,
+        general_purpose::STANDARD.encode(bytes)
+    );
+
+    let (ok, decoded, error) = match listener::parse_log(&line, &state.config, EventScheme::anchor()) {
+        Ok((event, _)) => (event == expected, Some(event), None),
+        Err(e) => (false, None, Some(e.to_string())),
+    };
+    let status = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let body = serde_json::json!({
+        "ok": ok,
+        "line": line,
+        "expected": expected,
+        "decoded": decoded,
+        "error": error,
+    });
+    (status, Json(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{events::EventKind, metrics::Metrics, EventRecord};
+    use std::sync::atomic::Ordering;
+
+    #[tokio::test]
+    async fn stats_reset_clears_totals_but_keeps_orders() {
+        let state = AppState::new(&Config::from_pairs(&[]));
+        let (order, party) = (Pubkey::new_unique().to_string(), Pubkey::new_unique().to_string());
+        state.publish(
+            None,
+            EventRecord::test(WebEvent::OrderCreated {
+                order: order.clone(),
+                order_id: 1,
+                customer: party.clone(),
+                amount: 500,
+            }),
+        );
+        state.publish(
+            None,
+            EventRecord::test(WebEvent::OrderCompleted {
+                order: Pubkey::new_unique().to_string(),
+                order_id: 2,
+                courier: party,
+                amount: 700,
+            }),
+        );
+        let counters = &state.program_stats[0].1;
+        counters.matched(EventKind::OrderCompleted);
+        Metrics::inc(&state.metrics.reconnect_count);
+
+        let Json(reply) = stats_reset_handler(State(state.clone())).await;
+        assert!(reply["reset_at"].is_i64());
+
+        assert!(state.leaderboard.top(10).is_empty());
+        let window = state.ticker.take();
+        assert_eq!((window.completed, window.total_volume), (0, 0));
+        assert_eq!(counters.matched.load(Ordering::Relaxed), 0);
+        assert_eq!(counters.last_seen_at.load(Ordering::Relaxed), 0);
+        assert_eq!(state.metrics.reconnect_count.load(Ordering::Relaxed), 0);
+        assert_eq!(state.orders.amount(&order), Some(500));
+    }
+}
@@ -0,0 +1,39 @@
+use crate::metrics::Metrics;
+use lru::LruCache;
+use solana_client::rpc_client::RpcClient;
+use std::{
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
+
+//
+// ---------------- Block time lookup (cached per slot)
+//
+// Many events share a slot, so `get_block_time` is called at most once per
+// slot while it stays in the LRU. Failed lookups are not cached.
+pub struct BlockTimeCache {
+    rpc: Arc<RpcClient>,
+    cache: Mutex<LruCache<u64, i64>>,
+}
+
+impl BlockTimeCache {
+    pub fn new(rpc: Arc<RpcClient>, size: usize) -> Self {
+        let size = NonZeroUsize::new(size).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            rpc,
+            cache: Mutex::new(LruCache::new(size)),
+        }
+    }
+
+    pub fn get(&self, slot: u64, metrics: &Metrics) -> Option<i64> {
+        if let Some(time) = self.cache.lock().unwrap().get(&slot) {
+            Metrics::inc(&metrics.block_time_cache_hits_total);
+            return Some(*time);
+        }
+
+        Metrics::inc(&metrics.block_time_cache_misses_total);
+        let time = self.rpc.get_block_time(slot).ok()?;
+        self.cache.lock().unwrap().put(slot, time);
+        Some(time)
+    }
+}
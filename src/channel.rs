@@ -0,0 +1,258 @@
+use crate::{config::LagPolicy, EventRecord};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    Notify,
+};
+
+pub type Tx = broadcast::Sender<EventRecord>;
+
+//
+// ---------------- Event channel
+//
+// A broadcast channel, plus (with `LagPolicy::DropOldest`) a single reader
+// that copies every event into per-client bounded queues. A slow client then
+// only loses its own oldest events instead of being disconnected.
+#[derive(Clone)]
+pub struct Channel {
+    pub tx: Tx,
+    fanout: Option<Arc<Fanout>>,
+    queue_size: usize,
+    /// Send times of the events the broadcast buffer can still hold, oldest
+    /// first.
+    sent_at: Arc<Mutex<VecDeque<Instant>>>,
+    capacity: usize,
+}
+
+impl Channel {
+    pub fn new(capacity: usize, policy: LagPolicy, queue_size: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        let fanout = match policy {
+            LagPolicy::Disconnect => None,
+            LagPolicy::DropOldest => Some(Fanout::spawn(&tx)),
+        };
+
+        Self {
+            tx,
+            fanout,
+            queue_size,
+            sent_at: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            // What tokio rounds the buffer size to.
+            capacity: capacity.next_power_of_two(),
+        }
+    }
+
+    pub fn send(&self, record: EventRecord) {
+        let mut sent_at = self.sent_at.lock().unwrap();
+        if sent_at.len() >= self.capacity {
+            sent_at.pop_front();
+        }
+        sent_at.push_back(Instant::now());
+        let _ = self.tx.send(record);
+    }
+
+    /// How long the oldest event some subscriber has not received yet has
+    /// been waiting, in the broadcast buffer or in a client queue.
+    pub fn oldest_unconsumed(&self) -> Option<Duration> {
+        let buffered = {
+            let sent_at = self.sent_at.lock().unwrap();
+            // Events the slowest receiver has yet to see are the newest ones.
+            match self.tx.len() {
+                0 => None,
+                pending => sent_at.get(sent_at.len().saturating_sub(pending)).copied(),
+            }
+        };
+        let queued = self.fanout.as_ref().and_then(|fanout| fanout.oldest_queued());
+        buffered.into_iter().chain(queued).min().map(|since| since.elapsed())
+    }
+
+    pub fn subscribe(&self) -> Subscription {
+        match &self.fanout {
+            None => Subscription::Direct(self.tx.subscribe()),
+            Some(fanout) => Subscription::Queued(fanout.register(self.queue_size)),
+        }
+    }
+}
+
+pub enum Delivery {
+    Event(EventRecord),
+    /// The client's own queue overflowed; this many oldest events were dropped.
+    Dropped(u64),
+    /// The client fell behind the shared broadcast buffer.
+    Lagged(u64),
+    Closed,
+}
+
+pub enum Subscription {
+    Direct(broadcast::Receiver<EventRecord>),
+    Queued(Arc<ClientQueue>),
+}
+
+impl Subscription {
+    pub async fn next(&mut self) -> Delivery {
+        match self {
+            Subscription::Direct(rx) => match rx.recv().await {
+                Ok(record) => Delivery::Event(record),
+                Err(RecvError::Lagged(n)) => Delivery::Lagged(n),
+                Err(RecvError::Closed) => Delivery::Closed,
+            },
+            Subscription::Queued(queue) => queue.next().await,
+        }
+    }
+}
+
+//
+// ---------------- Per-client queue
+//
+pub struct ClientQueue {
+    capacity: usize,
+    inner: Mutex<QueueState>,
+    notify: Notify,
+}
+
+#[derive(Default)]
+struct QueueState {
+    /// With the time each event was queued.
+    events: VecDeque<(Instant, EventRecord)>,
+    dropped: u64,
+    closed: bool,
+}
+
+impl ClientQueue {
+    fn push(&self, record: EventRecord) {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.events.len() >= self.capacity {
+                inner.events.pop_front();
+                inner.dropped += 1;
+            }
+            inner.events.push_back((Instant::now(), record));
+        }
+        self.notify.notify_one();
+    }
+
+    fn add_dropped(&self, n: u64) {
+        self.inner.lock().unwrap().dropped += n;
+        self.notify.notify_one();
+    }
+
+    fn close(&self) {
+        self.inner.lock().unwrap().closed = true;
+        self.notify.notify_one();
+    }
+
+    async fn next(&self) -> Delivery {
+        loop {
+            {
+                let mut inner = self.inner.lock().unwrap();
+                // Report drops before the events that follow the gap.
+                if inner.dropped > 0 {
+                    return Delivery::Dropped(std::mem::take(&mut inner.dropped));
+                }
+                if let Some((_, record)) = inner.events.pop_front() {
+                    return Delivery::Event(record);
+                }
+                if inner.closed {
+                    return Delivery::Closed;
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+}
+
+//
+// ---------------- Single broadcast reader
+//
+struct Fanout {
+    clients: Mutex<Vec<Weak<ClientQueue>>>,
+}
+
+impl Fanout {
+    fn spawn(tx: &Tx) -> Arc<Self> {
+        let fanout = Arc::new(Self {
+            clients: Mutex::new(Vec::new()),
+        });
+
+        let mut rx = tx.subscribe();
+        let reader = fanout.clone();
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(record) => reader.each(|q| q.push(record.clone())),
+                    // Only reachable if this task itself is starved.
+                    Err(RecvError::Lagged(n)) => reader.each(|q| q.add_dropped(n)),
+                    Err(RecvError::Closed) => {
+                        reader.each(|q| q.close());
+                        break;
+                    }
+                }
+            }
+        });
+
+        fanout
+    }
+
+    fn register(&self, capacity: usize) -> Arc<ClientQueue> {
+        let queue = Arc::new(ClientQueue {
+            capacity: capacity.max(1),
+            inner: Mutex::new(QueueState::default()),
+            notify: Notify::new(),
+        });
+        self.clients.lock().unwrap().push(Arc::downgrade(&queue));
+        queue
+    }
+
+    fn oldest_queued(&self) -> Option<Instant> {
+        let mut oldest = None;
+        self.each(|q| {
+            let front = q.inner.lock().unwrap().events.front().map(|(at, _)| *at);
+            oldest = oldest.into_iter().chain(front).min();
+        });
+        oldest
+    }
+
+    // Also prunes queues of disconnected clients.
+    fn each(&self, mut f: impl FnMut(&ClientQueue)) {
+        self.clients.lock().unwrap().retain(|q| match q.upgrade() {
+            Some(q) => {
+                f(&q);
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WebEvent;
+
+    #[test]
+    fn oldest_unconsumed_follows_the_slowest_receiver() {
+        let channel = Channel::new(4, LagPolicy::Disconnect, 1);
+        let (mut fast, mut slow) = (channel.tx.subscribe(), channel.tx.subscribe());
+        assert_eq!(channel.oldest_unconsumed(), None);
+
+        let record = EventRecord::test(WebEvent::OrderAccepted {
+            order: "o".to_string(),
+            courier: "c".to_string(),
+        });
+        channel.send(record.clone());
+        std::thread::sleep(Duration::from_millis(20));
+        channel.send(record);
+        fast.try_recv().unwrap();
+        fast.try_recv().unwrap();
+        assert!(channel.oldest_unconsumed().unwrap() >= Duration::from_millis(20));
+
+        slow.try_recv().unwrap();
+        assert!(channel.oldest_unconsumed().unwrap() < Duration::from_millis(20));
+        slow.try_recv().unwrap();
+        assert_eq!(channel.oldest_unconsumed(), None);
+    }
+}
@@ -0,0 +1,166 @@
+//! A typed consumer of the service's `/ws` stream, for Rust services that
+//! would otherwise hand-roll a WebSocket client (feature `client`).
+//!
+//! It expects the default encoding (`TAG_FORMAT=string`,
+//! `JSON_U64_MODE=none`); frames it can't decode as a `WebEvent`, such as
+//! `Dropped` notices, are skipped.
+
+use crate::{EventKind, WebEvent};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::warn;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Server-side filters, mapped onto the `/ws` routes and query parameters.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// Only this program (`/ws/:program_id`).
+    pub program_id: Option<String>,
+    /// Only this event type (`/ws/created`, …); ignored with `program_id`.
+    pub kind: Option<EventKind>,
+    /// `?min_amount=`.
+    pub min_amount: Option<u64>,
+}
+
+impl Filter {
+    fn url(&self, base: &str, since_seq: Option<u64>) -> String {
+        let path = match (&self.program_id, self.kind) {
+            (Some(id), _) => format!("/ws/{}", id),
+            (None, Some(EventKind::OrderCreated)) => "/ws/created".to_string(),
+            (None, Some(EventKind::OrderAccepted)) => "/ws/accepted".to_string(),
+            (None, Some(EventKind::OrderCompleted)) => "/ws/completed".to_string(),
+            (None, _) => "/ws".to_string(),
+        };
+        let query: Vec<String> = self
+            .min_amount
+            .map(|min| format!("min_amount={}", min))
+            .into_iter()
+            .chain(since_seq.map(|seq| format!("since_seq={}", seq)))
+            .collect();
+        let mut url = format!("{}{}", base.trim_end_matches('/'), path);
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query.join("&"));
+        }
+        url
+    }
+}
+
+/// An event frame; `seq` is kept to resume after a reconnect.
+#[derive(Deserialize)]
+struct Frame {
+    #[serde(flatten)]
+    event: WebEvent,
+    seq: u64,
+}
+
+/// Connects to the service at `url` (e.g. `ws://localhost:3000`) and yields
+/// its events. Reconnects with backoff whenever the connection fails or
+/// ends, resuming with `?since_seq` after the last event received, so
+/// nothing still in the server's replay buffer is missed. Must be called
+/// inside a Tokio runtime; the stream ends only when dropped.
+pub fn connect(url: &str, filter: Filter) -> impl Stream<Item = WebEvent> {
+    let (tx, rx) = mpsc::channel(1024);
+    let base = url.to_string();
+
+    tokio::spawn(async move {
+        let mut last_seq = None;
+        let mut backoff = INITIAL_BACKOFF;
+        while !tx.is_closed() {
+            let url = filter.url(&base, last_seq);
+            match connect_async(url.as_str()).await {
+                Ok((mut socket, _)) => {
+                    backoff = INITIAL_BACKOFF;
+                    while let Some(Ok(message)) = socket.next().await {
+                        let Message::Text(text) = message else {
+                            continue;
+                        };
+                        let Ok(frame) = serde_json::from_str::<Frame>(&text) else {
+                            continue;
+                        };
+                        last_seq = Some(frame.seq);
+                        if tx.send(frame.event).await.is_err() {
+                            return;
+                        }
+                    }
+                    warn!("Event stream {} closed, reconnecting", url);
+                }
+                Err(e) => warn!("Connecting to {} failed: {}, retrying in {:?}", url, e, backoff),
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::SinkExt;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::{
+        accept_hdr_async,
+        tungstenite::handshake::server::{Request, Response},
+    };
+
+    #[test]
+    fn builds_urls_from_filters() {
+        let filter = Filter {
+            kind: Some(EventKind::OrderCompleted),
+            min_amount: Some(5),
+            ..Filter::default()
+        };
+        assert_eq!(
+            filter.url("ws://host:3000/", Some(7)),
+            "ws://host:3000/ws/completed?min_amount=5&since_seq=7"
+        );
+        assert_eq!(Filter::default().url("ws://host", None), "ws://host/ws");
+    }
+
+    // The handshake callback's error type is tungstenite's, not ours.
+    #[allow(clippy::result_large_err)]
+    #[tokio::test]
+    async fn reconnects_and_resumes_after_the_last_seq() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let event = |order: &str| WebEvent::OrderAccepted {
+            order: order.to_string(),
+            courier: "c".to_string(),
+        };
+
+        let (first, second) = (event("first"), event("second"));
+        let server = tokio::spawn(async move {
+            let mut paths = Vec::new();
+            for (seq, event) in [(1, first), (2, second)] {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut path = String::new();
+                let mut socket = accept_hdr_async(stream, |req: &Request, res: Response| {
+                    path = req.uri().to_string();
+                    Ok(res)
+                })
+                .await
+                .unwrap();
+                paths.push(path);
+                let mut frame = serde_json::to_value(&event).unwrap();
+                frame["seq"] = seq.into();
+                socket.send(Message::Text(frame.to_string())).await.unwrap();
+                // Ending the connection makes the client reconnect.
+                socket.close(None).await.unwrap();
+            }
+            paths
+        });
+
+        let mut events = std::pin::pin!(connect(&url, Filter::default()));
+        assert_eq!(events.next().await, Some(event("first")));
+        assert_eq!(events.next().await, Some(event("second")));
+        assert_eq!(server.await.unwrap(), ["/ws", "/ws?since_seq=1"]);
+    }
+}
@@ -0,0 +1,56 @@
+use crate::orders::now_secs;
+use serde::Serialize;
+use std::{collections::VecDeque, sync::Mutex};
+
+//
+// ---------------- Decode dead letters
+//
+// Event data whose discriminator matched but whose payload didn't
+// deserialize, kept verbatim for offline reproduction. Bounded; the oldest
+// entry is evicted first.
+pub struct DeadLetters {
+    capacity: usize,
+    entries: Mutex<VecDeque<DeadLetter>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadLetter {
+    pub signature: String,
+    pub slot: u64,
+    pub raw_base64: String,
+    pub error: String,
+    /// Unix seconds.
+    pub received_at: i64,
+}
+
+impl DeadLetters {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub fn push(&self, signature: &str, slot: u64, raw_base64: &str, error: String) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(DeadLetter {
+            signature: signature.to_string(),
+            slot,
+            raw_base64: raw_base64.to_string(),
+            error,
+            received_at: now_secs(),
+        });
+    }
+
+    /// Oldest first.
+    pub fn list(&self) -> Vec<DeadLetter> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+}
@@ -0,0 +1,81 @@
+use crate::{metrics::Metrics, pause::Held, state::AppState, WebEvent};
+use solana_sdk::pubkey::Pubkey;
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+pub type DebounceTx = mpsc::Sender<Held>;
+
+//
+// ---------------- Per-order debounce (`BROADCAST_DEBOUNCE_MS`)
+//
+// Holds each order's latest event until the order has been quiet for
+// `quiet`, so a burst of transitions reaches clients as its final state
+// only. A held `OrderCompleted` is terminal: it is never replaced by an
+// earlier-stage event arriving late. Only broadcasting is debounced;
+// projections and the replay buffer already saw every event.
+pub fn spawn(state: AppState, rx: mpsc::Receiver<Held>, quiet: Duration) {
+    thread::spawn(move || {
+        let mut pending: HashMap<(Option<Pubkey>, String), (Instant, Held)> = HashMap::new();
+
+        loop {
+            let timeout = pending
+                .values()
+                .map(|(deadline, _)| deadline.saturating_duration_since(Instant::now()))
+                .min()
+                .unwrap_or(quiet);
+
+            match rx.recv_timeout(timeout) {
+                Ok(item) => {
+                    let key = (item.0, item.1.event.order().to_string());
+                    let deadline = Instant::now() + quiet;
+                    match pending.get_mut(&key) {
+                        Some((held_until, held)) => {
+                            *held_until = deadline;
+                            if is_terminal(&held.1.event) && !is_terminal(&item.1.event) {
+                                Metrics::inc(&state.metrics.debounced_events_total);
+                                continue;
+                            }
+                            *held = item;
+                            Metrics::inc(&state.metrics.debounced_events_total);
+                        }
+                        None => {
+                            pending.insert(key, (deadline, item));
+                        }
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    release(&state, pending.drain().map(|(_, (_, item))| item).collect());
+                    break;
+                }
+            }
+
+            let now = Instant::now();
+            let mut due = Vec::new();
+            pending.retain(|_, (deadline, item)| {
+                if *deadline > now {
+                    return true;
+                }
+                due.push(item.clone());
+                false
+            });
+            release(&state, due);
+        }
+    });
+}
+
+fn is_terminal(event: &WebEvent) -> bool {
+    matches!(event, WebEvent::OrderCompleted { .. })
+}
+
+/// In publish order.
+fn release(state: &AppState, mut items: Vec<Held>) {
+    items.sort_by_key(|(_, record)| record.seq);
+    for item in items {
+        state.release(item);
+    }
+}
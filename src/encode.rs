@@ -0,0 +1,202 @@
+use crate::{
+    config::{Config, TagFormat, U64Mode},
+    EventRecord,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::str::FromStr;
+
+//
+// ---------------- Outbound JSON encoding
+//
+// Single place where broadcast records become JSON text, so every output
+// (WebSocket, stdout, REST) honours the same formatting options.
+
+/// Per-connection encoding options on top of the global ones in `Config`.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// Add the original base64 event data as `raw_base64`.
+    pub include_raw: bool,
+    /// Indented multi-line JSON, for reading the stream by hand.
+    pub pretty: bool,
+    /// Only these top-level fields (plus the type tag); `None` sends all.
+    pub fields: Option<Vec<String>>,
+    pub protocol: Protocol,
+    pub encoding: Encoding,
+}
+
+/// How a WebSocket client gets order events; everything else is always
+/// JSON text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    #[default]
+    Json,
+    /// `proto/events.proto` messages in Binary frames.
+    Protobuf,
+}
+
+/// Event message layout, negotiated per connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Protocol {
+    /// Event fields and chain context side by side.
+    #[default]
+    V1,
+    /// Event fields under `data`; tag and context at the top level.
+    V2,
+}
+
+impl Protocol {
+    pub fn name(self) -> &'static str {
+        match self {
+            Protocol::V1 => "bds-v1",
+            Protocol::V2 => "bds-v2",
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "bds-v1" => Ok(Protocol::V1),
+            "bds-v2" => Ok(Protocol::V2),
+            _ => Err(()),
+        }
+    }
+}
+
+pub fn to_json(record: &EventRecord, config: &Config, options: &Options) -> serde_json::Result<String> {
+    if config.tag_format == TagFormat::String
+        && config.u64_mode == U64Mode::None
+        && !options.include_raw
+        && options.fields.is_none()
+        && options.protocol == Protocol::V1
+    {
+        return to_text(record, options.pretty);
+    }
+
+    let mut value = to_value(record, config.u64_mode)?;
+    if let Some(obj) = value.as_object_mut() {
+        if config.tag_format == TagFormat::Numeric {
+            obj.remove("type");
+            obj.insert("t".to_string(), record.event.type_code().into());
+        }
+        if options.include_raw && let Some(raw) = &record.raw_base64 {
+            obj.insert("raw_base64".to_string(), raw.as_str().into());
+        }
+        if let Some(fields) = &options.fields {
+            obj.retain(|key, _| key == "type" || key == "t" || fields.contains(key));
+        }
+        if options.protocol == Protocol::V2 {
+            let event = serde_json::to_value(&record.event)?;
+            let event_fields = event.as_object().into_iter().flat_map(Map::keys);
+            let data: Map<String, Value> = event_fields
+                .filter(|key| *key != "type")
+                .filter_map(|key| obj.remove_entry(key))
+                .collect();
+            obj.insert("data".to_string(), data.into());
+        }
+    }
+    to_text(&value, options.pretty)
+}
+
+pub fn to_text<T: Serialize>(value: &T, pretty: bool) -> serde_json::Result<String> {
+    if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    }
+}
+
+/// `to_value`, with the u64 fields `JSON_U64_MODE` selects turned into strings.
+pub fn to_value<T: Serialize>(value: &T, mode: U64Mode) -> serde_json::Result<Value> {
+    let mut value = serde_json::to_value(value)?;
+    if mode != U64Mode::None {
+        stringify(&mut value, mode);
+    }
+    Ok(value)
+}
+
+/// u64 fields in emitted JSON, by group.
+const ID_FIELDS: &[&str] = &["order_id", "slot", "seq", "from_seq", "since_seq", "last_seq"];
+const AMOUNT_FIELDS: &[&str] = &["amount", "total_volume", "volume", "min_amount"];
+
+fn stringify(value: &mut Value, mode: U64Mode) {
+    match value {
+        Value::Array(items) => items.iter_mut().for_each(|v| stringify(v, mode)),
+        Value::Object(obj) => {
+            for (key, v) in obj.iter_mut() {
+                let selected = match mode {
+                    U64Mode::None => false,
+                    U64Mode::AllStrings => {
+                        ID_FIELDS.contains(&key.as_str()) || AMOUNT_FIELDS.contains(&key.as_str())
+                    }
+                    U64Mode::AmountsOnly => AMOUNT_FIELDS.contains(&key.as_str()),
+                    U64Mode::IdsOnly => ID_FIELDS.contains(&key.as_str()),
+                };
+                match v {
+                    Value::Number(n) if selected => *v = Value::String(n.to_string()),
+                    _ => stringify(v, mode),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WebEvent;
+
+    #[test]
+    fn projects_fields_and_keeps_the_tag() {
+        let record = EventRecord {
+            slot: Some(9),
+            seq: 1,
+            ..EventRecord::test(WebEvent::OrderCreated {
+                order: "o".to_string(),
+                order_id: 7,
+                customer: "c".to_string(),
+                amount: 5,
+            })
+        };
+        let options = Options {
+            fields: Some(vec!["order_id".to_string(), "slot".to_string(), "nope".to_string()]),
+            ..Options::default()
+        };
+        let config = Config::from_pairs(&[]);
+        assert_eq!(
+            to_json(&record, &config, &options).unwrap(),
+            r#"{"order_id":7,"slot":9,"type":"OrderCreated"}"#
+        );
+
+        let config = Config::from_pairs(&[("TAG_FORMAT", "numeric")]);
+        assert_eq!(
+            to_json(&record, &config, &options).unwrap(),
+            r#"{"order_id":7,"slot":9,"t":0}"#
+        );
+    }
+
+    #[test]
+    fn v2_nests_event_fields_under_data() {
+        let record = EventRecord {
+            slot: Some(9),
+            seq: 3,
+            ..EventRecord::test(WebEvent::OrderAccepted {
+                order: "o".to_string(),
+                courier: "c".to_string(),
+            })
+        };
+        let options = Options {
+            protocol: Protocol::V2,
+            ..Options::default()
+        };
+        assert_eq!(
+            to_json(&record, &Config::from_pairs(&[]), &options).unwrap(),
+            r#"{"data":{"courier":"c","order":"o"},"seq":3,"slot":9,"type":"OrderAccepted"}"#
+        );
+    }
+}
@@ -0,0 +1,511 @@
+use crate::finality::Finality;
+use anchor_lang::prelude::*;
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::LazyLock;
+
+//
+// ---------------- Anchor event structs
+//
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+pub struct OrderCreated {
+    pub order: Pubkey,
+    pub order_id: u64,
+    pub customer: Pubkey,
+    pub amount: u64,
+}
+
+#[derive(Debug, BorshDeserialize)]
+pub struct OrderAccepted {
+    pub order: Pubkey,
+    pub courier: Pubkey,
+}
+
+#[derive(Debug, BorshDeserialize)]
+pub struct OrderCompleted {
+    pub order: Pubkey,
+    pub order_id: u64,
+    pub courier: Pubkey,
+    pub amount: u64,
+}
+
+/// Borsh field order and types of the structs above, in IDL terms; checked
+/// against `IDL_PATH` at startup. Keep in sync with the structs.
+pub const EVENT_SCHEMAS: [(&str, &[(&str, &str)]); 3] = [
+    (
+        "OrderCreated",
+        &[("order", "pubkey"), ("order_id", "u64"), ("customer", "pubkey"), ("amount", "u64")],
+    ),
+    ("OrderAccepted", &[("order", "pubkey"), ("courier", "pubkey")]),
+    (
+        "OrderCompleted",
+        &[("order", "pubkey"), ("order_id", "u64"), ("courier", "pubkey"), ("amount", "u64")],
+    ),
+];
+
+//
+// ---------------- Anchor order account (`ORDER_BACKFILL`)
+//
+/// Leading fields of the program's `Order` account, after its 8-byte
+/// discriminator; whatever follows them is not read.
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+pub struct OrderAccount {
+    pub order_id: u64,
+    pub customer: Pubkey,
+    pub amount: u64,
+}
+
+impl OrderAccount {
+    /// `None` unless `data` is an `Order` account long enough for the fields.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let (disc, mut fields) = data.split_at_checked(8)?;
+        if disc != account_discriminator("Order") {
+            return None;
+        }
+        Self::deserialize(&mut fields).ok()
+    }
+}
+
+//
+// ---------------- Web JSON events
+//
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum WebEvent {
+    OrderCreated {
+        order: String,
+        order_id: u64,
+        customer: String,
+        amount: u64,
+    },
+    OrderAccepted {
+        order: String,
+        courier: String,
+    },
+    OrderCompleted {
+        order: String,
+        order_id: u64,
+        courier: String,
+        amount: u64,
+    },
+    /// Activity summary emitted by the service itself every `TICK_INTERVAL_SECS`.
+    Tick {
+        window_secs: u64,
+        created: u64,
+        accepted: u64,
+        completed: u64,
+        /// Sum of `amount` over the window's `OrderCompleted` events.
+        total_volume: u64,
+    },
+    /// Completions per minute over the last `window_secs` fell under
+    /// `SLA_MIN_COMPLETIONS_PER_MIN` and stayed there for `SLA_HOLD_SECS`.
+    SlaAlert {
+        window_secs: u64,
+        rate: f64,
+        threshold: f64,
+    },
+    /// The rate is back at or above the threshold after an `SlaAlert`.
+    SlaRecovered {
+        window_secs: u64,
+        rate: f64,
+        threshold: f64,
+    },
+    /// `program_id`'s source is back after `gap_ms` without one; its events
+    /// from that time are missing. `last_seq` is the last event published
+    /// before this notice. Only sent with `NOTIFY_RECONNECT`.
+    SourceReconnected {
+        program_id: String,
+        gap_ms: u64,
+        last_seq: u64,
+    },
+    /// `slot`, where transaction `signature`'s events were published from,
+    /// was abandoned before it was finalized; those events never happened.
+    /// Only sent with `ROLLBACK_DETECTION`.
+    Reverted {
+        signature: String,
+        slot: u64,
+    },
+    /// A `Program log:` line of the program that isn't event data, for
+    /// debugging. Only sent with `EMIT_PROGRAM_LOGS`.
+    ProgramLog {
+        signature: String,
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum EventKind {
+    OrderCreated,
+    OrderAccepted,
+    OrderCompleted,
+    Tick,
+    SlaAlert,
+    SlaRecovered,
+    SourceReconnected,
+    Reverted,
+    ProgramLog,
+}
+
+impl EventKind {
+    /// Event types the program emits; `Tick` and the SLA events are the
+    /// service's own.
+    pub const ALL: [EventKind; 3] = [
+        EventKind::OrderCreated,
+        EventKind::OrderAccepted,
+        EventKind::OrderCompleted,
+    ];
+
+    /// Stable numeric event code used by `TAG_FORMAT=numeric`.
+    /// Never renumber existing kinds; new kinds get the next free code.
+    pub fn code(self) -> u16 {
+        match self {
+            EventKind::OrderCreated => 0,
+            EventKind::OrderAccepted => 1,
+            EventKind::OrderCompleted => 2,
+            EventKind::Tick => 3,
+            EventKind::SlaAlert => 4,
+            EventKind::SlaRecovered => 5,
+            EventKind::SourceReconnected => 6,
+            EventKind::Reverted => 7,
+            EventKind::ProgramLog => 8,
+        }
+    }
+}
+
+impl std::str::FromStr for EventKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "OrderCreated" => Ok(EventKind::OrderCreated),
+            "OrderAccepted" => Ok(EventKind::OrderAccepted),
+            "OrderCompleted" => Ok(EventKind::OrderCompleted),
+            "Tick" => Ok(EventKind::Tick),
+            "SlaAlert" => Ok(EventKind::SlaAlert),
+            "SlaRecovered" => Ok(EventKind::SlaRecovered),
+            "SourceReconnected" => Ok(EventKind::SourceReconnected),
+            "Reverted" => Ok(EventKind::Reverted),
+            "ProgramLog" => Ok(EventKind::ProgramLog),
+            _ => Err(()),
+        }
+    }
+}
+
+impl WebEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            WebEvent::OrderCreated { .. } => EventKind::OrderCreated,
+            WebEvent::OrderAccepted { .. } => EventKind::OrderAccepted,
+            WebEvent::OrderCompleted { .. } => EventKind::OrderCompleted,
+            WebEvent::Tick { .. } => EventKind::Tick,
+            WebEvent::SlaAlert { .. } => EventKind::SlaAlert,
+            WebEvent::SlaRecovered { .. } => EventKind::SlaRecovered,
+            WebEvent::SourceReconnected { .. } => EventKind::SourceReconnected,
+            WebEvent::Reverted { .. } => EventKind::Reverted,
+            WebEvent::ProgramLog { .. } => EventKind::ProgramLog,
+        }
+    }
+
+    /// Published by the service itself rather than decoded from the chain;
+    /// `ProgramLog` counts as such, being about no order either.
+    pub fn is_own(&self) -> bool {
+        !EventKind::ALL.contains(&self.kind())
+    }
+
+    /// Stable numeric type code, see `EventKind::code`.
+    pub fn type_code(&self) -> u16 {
+        self.kind().code()
+    }
+
+    /// Empty for the service's own events, which are about no order in
+    /// particular.
+    pub fn order(&self) -> &str {
+        match self {
+            WebEvent::OrderCreated { order, .. }
+            | WebEvent::OrderAccepted { order, .. }
+            | WebEvent::OrderCompleted { order, .. } => order,
+            _ => "",
+        }
+    }
+
+    pub fn order_id(&self) -> Option<u64> {
+        match self {
+            WebEvent::OrderCreated { order_id, .. }
+            | WebEvent::OrderCompleted { order_id, .. } => Some(*order_id),
+            _ => None,
+        }
+    }
+
+    pub fn amount(&self) -> Option<u64> {
+        match self {
+            WebEvent::OrderCreated { amount, .. }
+            | WebEvent::OrderCompleted { amount, .. } => Some(*amount),
+            _ => None,
+        }
+    }
+
+    pub fn customer(&self) -> Option<&str> {
+        match self {
+            WebEvent::OrderCreated { customer, .. } => Some(customer),
+            _ => None,
+        }
+    }
+
+    pub fn courier(&self) -> Option<&str> {
+        match self {
+            WebEvent::OrderAccepted { courier, .. }
+            | WebEvent::OrderCompleted { courier, .. } => Some(courier),
+            _ => None,
+        }
+    }
+}
+
+//
+// ---------------- Broadcast record: event + chain context
+//
+// Serializes flat: the event's fields and `type` tag sit next to the context.
+#[derive(Debug, Serialize, Clone)]
+pub struct EventRecord {
+    #[serde(flatten)]
+    pub event: WebEvent,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slot: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_time: Option<i64>,
+    /// Position of the transaction in its block; only set with
+    /// `ORDERING_METADATA`, and only when the block could be fetched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_index: Option<u32>,
+    /// Position of the event's log line in the transaction's logs; only set
+    /// with `ORDERING_METADATA`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_index: Option<u32>,
+    /// Whether `order` matches the PDA derived from `ORDER_PDA_SEEDS`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pda_valid: Option<bool>,
+    /// Commitment the event was seen at; only set with `DUAL_COMMITMENT`,
+    /// `PROGRAM_COMMITMENT` or `ROLLBACK_DETECTION`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finality: Option<Finality>,
+    /// Original base64 event data; only written out on request.
+    #[serde(skip)]
+    pub raw_base64: Option<String>,
+    /// When the service received the event, in microseconds since the Unix
+    /// epoch; only set with `RECEIVED_AT`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub received_at: Option<u64>,
+    /// Set for events that did not come from the chain.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<EventSource>,
+    /// Publish order, for `?since_seq` resume; assigned on publish, 0 before.
+    /// Strictly increasing over all event types. Restarts at 1 with the
+    /// process.
+    pub seq: u64,
+}
+
+#[cfg(test)]
+impl EventRecord {
+    /// `event` without any metadata, as published before `seq` is assigned.
+    pub(crate) fn test(event: WebEvent) -> Self {
+        Self {
+            event,
+            slot: None,
+            signature: None,
+            block_time: None,
+            tx_index: None,
+            log_index: None,
+            pda_valid: None,
+            finality: None,
+            raw_base64: None,
+            received_at: None,
+            source: None,
+            seq: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventSource {
+    /// Posted to `/ingest` by another system.
+    External,
+}
+
+//
+// ---------------- Anchor event discriminator
+//
+pub fn event_discriminator(name: &str) -> [u8; 8] {
+    discriminator("event", name)
+}
+
+pub fn account_discriminator(name: &str) -> [u8; 8] {
+    discriminator("account", name)
+}
+
+fn discriminator(prefix: &str, name: &str) -> [u8; 8] {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}:{}", prefix, name));
+    let hash = hasher.finalize();
+    let mut disc = [0u8; 8];
+    disc.copy_from_slice(&hash[..8]);
+    disc
+}
+
+/// How a program names its events on chain: each discriminator is the first
+/// 8 bytes of `sha256("<prefix>:<name>")`. Standard Anchor programs use
+/// `event` and the struct names; forks may rename either.
+#[derive(Debug, Clone, Serialize)]
+pub struct EventScheme {
+    pub prefix: String,
+    /// In `EventKind::ALL` order.
+    pub names: [String; EventKind::ALL.len()],
+    #[serde(skip)]
+    discriminators: [[u8; 8]; EventKind::ALL.len()],
+}
+
+static ANCHOR: LazyLock<EventScheme> = LazyLock::new(|| {
+    EventScheme::new("event", EventKind::ALL.map(|kind| format!("{:?}", kind)))
+});
+
+impl EventScheme {
+    pub fn new(prefix: &str, names: [String; EventKind::ALL.len()]) -> Self {
+        let discriminators = std::array::from_fn(|i| discriminator(prefix, &names[i]));
+        Self {
+            prefix: prefix.to_string(),
+            names,
+            discriminators,
+        }
+    }
+
+    pub fn anchor() -> &'static Self {
+        &ANCHOR
+    }
+
+    pub fn discriminator(&self, kind: EventKind) -> Option<[u8; 8]> {
+        let i = EventKind::ALL.iter().position(|k| *k == kind)?;
+        Some(self.discriminators[i])
+    }
+
+    pub fn kind(&self, disc: &[u8]) -> Option<EventKind> {
+        let i = self.discriminators.iter().position(|d| d == disc)?;
+        Some(EventKind::ALL[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Clients persist and dispatch on these codes; changing one is a breaking
+    // change. Add new variants here with their new code.
+    #[test]
+    fn type_codes_are_stable() {
+        let order = || "order".to_string();
+        let events = [
+            (
+                WebEvent::OrderCreated {
+                    order: order(),
+                    order_id: 1,
+                    customer: "customer".to_string(),
+                    amount: 1,
+                },
+                0,
+            ),
+            (
+                WebEvent::OrderAccepted {
+                    order: order(),
+                    courier: "courier".to_string(),
+                },
+                1,
+            ),
+            (
+                WebEvent::OrderCompleted {
+                    order: order(),
+                    order_id: 1,
+                    courier: "courier".to_string(),
+                    amount: 1,
+                },
+                2,
+            ),
+            (
+                WebEvent::Tick {
+                    window_secs: 60,
+                    created: 1,
+                    accepted: 1,
+                    completed: 1,
+                    total_volume: 1,
+                },
+                3,
+            ),
+            (
+                WebEvent::SlaAlert {
+                    window_secs: 300,
+                    rate: 0.5,
+                    threshold: 1.0,
+                },
+                4,
+            ),
+            (
+                WebEvent::SlaRecovered {
+                    window_secs: 300,
+                    rate: 1.5,
+                    threshold: 1.0,
+                },
+                5,
+            ),
+            (
+                WebEvent::SourceReconnected {
+                    program_id: "program".to_string(),
+                    gap_ms: 1500,
+                    last_seq: 1,
+                },
+                6,
+            ),
+            (
+                WebEvent::Reverted {
+                    signature: "sig".to_string(),
+                    slot: 1200,
+                },
+                7,
+            ),
+            (
+                WebEvent::ProgramLog {
+                    signature: "sig".to_string(),
+                    message: "Instruction: Accept".to_string(),
+                },
+                8,
+            ),
+        ];
+
+        for (event, code) in &events {
+            assert_eq!(event.type_code(), *code, "{:?}", event.kind());
+        }
+        // `ALL` plus the service's own events.
+        assert_eq!(events.len(), EventKind::ALL.len() + 6, "every variant needs a pinned code");
+    }
+
+    #[test]
+    fn decodes_the_leading_fields_of_order_accounts() {
+        let account = OrderAccount {
+            order_id: 7,
+            customer: Pubkey::new_unique(),
+            amount: 100,
+        };
+        let mut data = account_discriminator("Order").to_vec();
+        data.extend(borsh::to_vec(&account).unwrap());
+        // Later fields, e.g. the courier, are not read.
+        data.extend([1; 33]);
+        let decoded = OrderAccount::decode(&data).unwrap();
+        assert_eq!(
+            (decoded.order_id, decoded.customer, decoded.amount),
+            (7, account.customer, 100)
+        );
+
+        assert!(OrderAccount::decode(&data[..20]).is_none());
+        data[..8].copy_from_slice(&account_discriminator("Courier"));
+        assert!(OrderAccount::decode(&data).is_none());
+    }
+}
@@ -0,0 +1,199 @@
+use crate::events::EventKind;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use std::{num::NonZeroUsize, sync::Mutex};
+
+/// Signatures remembered per program for deduplication.
+pub(crate) const DEDUP_CAPACITY: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Finality {
+    Processed,
+    Confirmed,
+    Finalized,
+}
+
+impl Finality {
+    pub fn commitment(self) -> CommitmentConfig {
+        match self {
+            Finality::Processed => CommitmentConfig::processed(),
+            Finality::Confirmed => CommitmentConfig::confirmed(),
+            Finality::Finalized => CommitmentConfig::finalized(),
+        }
+    }
+}
+
+impl std::str::FromStr for Finality {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "processed" => Ok(Finality::Processed),
+            "confirmed" => Ok(Finality::Confirmed),
+            "finalized" => Ok(Finality::Finalized),
+            _ => Err(()),
+        }
+    }
+}
+
+/// `DUAL_COMMITMENT_POLICY`: which copies of an event `DUAL_COMMITMENT`
+/// emits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinalityPolicy {
+    /// Once at `confirmed`, again at `finalized`.
+    EmitBoth,
+    /// Only at `finalized`; the `confirmed` subscription isn't opened.
+    FinalizedOnly,
+    /// Once, at the first level it is seen at; `finalized` fills in what
+    /// the `confirmed` subscription missed.
+    ConfirmedOnly,
+}
+
+impl std::str::FromStr for FinalityPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "emit_both" => Ok(FinalityPolicy::EmitBoth),
+            "finalized_only" => Ok(FinalityPolicy::FinalizedOnly),
+            "confirmed_only" => Ok(FinalityPolicy::ConfirmedOnly),
+            _ => Err(()),
+        }
+    }
+}
+
+//
+// ---------------- Dual-commitment deduplication
+//
+// With `DUAL_COMMITMENT` each program has a `confirmed` and a `finalized`
+// subscription. Under `emit_both` an event is let through once per level,
+// and never at `confirmed` after it was already seen `finalized`; under
+// `confirmed_only` only the first time it is seen at all. Events are keyed by
+// signature and discriminator (as the event type it resolves to under the
+// program's scheme), so one copy missing an event, e.g. to truncated logs,
+// doesn't decide for the transaction's other events. The program is part of
+// the key too, as programs sharing a transaction may share discriminators.
+pub struct FinalityTracker {
+    policy: FinalityPolicy,
+    seen: Mutex<LruCache<(Pubkey, String, EventKind), Seen>>,
+}
+
+/// The levels an event was let through at, first and latest.
+#[derive(Clone, Copy)]
+struct Seen {
+    first: Finality,
+    latest: Finality,
+}
+
+impl FinalityTracker {
+    pub fn new(policy: FinalityPolicy) -> Self {
+        let size = NonZeroUsize::new(DEDUP_CAPACITY).unwrap();
+        Self {
+            policy,
+            seen: Mutex::new(LruCache::new(size)),
+        }
+    }
+
+    /// Whether the policy drops everything seen at `finality`, so a
+    /// notification at that level needn't be looked at.
+    pub fn drops_level(&self, finality: Finality) -> bool {
+        self.policy == FinalityPolicy::FinalizedOnly && finality < Finality::Finalized
+    }
+
+    /// Whether the `kind` events of `signature` at `finality` should be
+    /// emitted. Asked once per notification and kind: a transaction emitting
+    /// the same event twice gets the same answer for both.
+    pub fn admit(&self, program_id: &Pubkey, signature: &str, kind: EventKind, finality: Finality) -> bool {
+        if self.drops_level(finality) {
+            return false;
+        }
+        let mut seen = self.seen.lock().unwrap();
+        let key = (*program_id, signature.to_string(), kind);
+        let prev = seen.get(&key).copied();
+        let suppressed = match self.policy {
+            FinalityPolicy::ConfirmedOnly => prev.is_some(),
+            _ => prev.is_some_and(|prev| prev.latest >= finality),
+        };
+        if suppressed {
+            return false;
+        }
+        let first = prev.map_or(finality, |prev| prev.first);
+        seen.put(key, Seen { first, latest: finality });
+        true
+    }
+
+    /// Whether the copy of a `kind` event of `signature` at `finality`
+    /// repeats one already let through at a lower level, i.e. the `finalized`
+    /// copy under `emit_both`. The projections count each event once and skip
+    /// such repeats.
+    pub fn is_repeat(&self, program_id: &Pubkey, signature: &str, kind: EventKind, finality: Finality) -> bool {
+        let seen = self.seen.lock().unwrap();
+        seen.peek(&(*program_id, signature.to_string(), kind))
+            .is_some_and(|seen| seen.first < finality)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn admitted(policy: FinalityPolicy, levels: &[Finality]) -> Vec<bool> {
+        let tracker = FinalityTracker::new(policy);
+        let program_id = Pubkey::new_unique();
+        levels
+            .iter()
+            .map(|level| tracker.admit(&program_id, "sig", EventKind::OrderCreated, *level))
+            .collect()
+    }
+
+    #[test]
+    fn policies_pick_which_copies_go_out() {
+        use Finality::{Confirmed, Finalized};
+        let arrivals = [Confirmed, Confirmed, Finalized, Finalized];
+        assert_eq!(admitted(FinalityPolicy::EmitBoth, &arrivals), [true, false, true, false]);
+        assert_eq!(admitted(FinalityPolicy::FinalizedOnly, &arrivals), [false, false, true, false]);
+        assert_eq!(admitted(FinalityPolicy::ConfirmedOnly, &arrivals), [true, false, false, false]);
+
+        // `finalized` first: never followed by a `confirmed` copy, and under
+        // `confirmed_only` it is the one copy.
+        assert_eq!(admitted(FinalityPolicy::EmitBoth, &[Finalized, Confirmed]), [true, false]);
+        assert_eq!(admitted(FinalityPolicy::ConfirmedOnly, &[Finalized, Confirmed]), [true, false]);
+    }
+
+    #[test]
+    fn only_the_later_copy_is_a_repeat() {
+        use Finality::{Confirmed, Finalized};
+        let tracker = FinalityTracker::new(FinalityPolicy::EmitBoth);
+        let program_id = Pubkey::new_unique();
+        assert!(tracker.admit(&program_id, "a", EventKind::OrderCreated, Confirmed));
+        assert!(!tracker.is_repeat(&program_id, "a", EventKind::OrderCreated, Confirmed));
+        assert!(tracker.admit(&program_id, "a", EventKind::OrderCreated, Finalized));
+        assert!(tracker.is_repeat(&program_id, "a", EventKind::OrderCreated, Finalized));
+
+        // Finalized first, e.g. the confirmed copy was missed: counted.
+        assert!(tracker.admit(&program_id, "b", EventKind::OrderCreated, Finalized));
+        assert!(!tracker.is_repeat(&program_id, "b", EventKind::OrderCreated, Finalized));
+    }
+
+    #[test]
+    fn each_event_type_of_a_transaction_is_decided_on_its_own() {
+        use Finality::{Confirmed, Finalized};
+        let tracker = FinalityTracker::new(FinalityPolicy::ConfirmedOnly);
+        let program_id = Pubkey::new_unique();
+        // The confirmed copy only had the creation, e.g. its logs were cut.
+        assert!(tracker.admit(&program_id, "tx", EventKind::OrderCreated, Confirmed));
+        assert!(!tracker.admit(&program_id, "tx", EventKind::OrderCreated, Finalized));
+        assert!(tracker.admit(&program_id, "tx", EventKind::OrderAccepted, Finalized));
+
+        let tracker = FinalityTracker::new(FinalityPolicy::EmitBoth);
+        assert!(tracker.admit(&program_id, "tx", EventKind::OrderCreated, Confirmed));
+        assert!(tracker.admit(&program_id, "tx", EventKind::OrderCreated, Finalized));
+        assert!(tracker.admit(&program_id, "tx", EventKind::OrderAccepted, Finalized));
+        assert!(tracker.is_repeat(&program_id, "tx", EventKind::OrderCreated, Finalized));
+        // First seen finalized: counted by the projections.
+        assert!(!tracker.is_repeat(&program_id, "tx", EventKind::OrderAccepted, Finalized));
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{
+    config::Config,
+    events::{event_discriminator, EVENT_SCHEMAS},
+    log_style::{self, icon},
+};
+use serde_json::Value;
+use std::fs;
+use tracing::{info, warn};
+
+//
+// ---------------- IDL schema check (`IDL_PATH`)
+//
+// Compares the hardcoded event layouts with the program's Anchor IDL, so
+// schema drift shows up at startup instead of as decode failures. Reads both
+// the current IDL format (fields under `types`, explicit discriminators) and
+// the pre-0.30 one (fields inline in `events`, camelCase names).
+
+/// Logs every difference; with `IDL_STRICT` any difference is an error.
+pub fn check_idl(config: &Config) -> Result<(), String> {
+    log_style::init(config.log_style);
+    let Some(path) = &config.idl_path else {
+        return Ok(());
+    };
+    let idl = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|s| serde_json::from_str(&s).map_err(|e| e.to_string()))
+        .map_err(|e| format!("cannot read IDL_PATH {}: {}", path.display(), e))?;
+
+    let mismatches = compare(&idl);
+    if mismatches.is_empty() {
+        info!("{} Event layouts match the IDL {}", icon("🔎"), path.display());
+        return Ok(());
+    }
+    for mismatch in &mismatches {
+        warn!("{} IDL mismatch: {}", icon("⚠️"), mismatch);
+    }
+    if config.idl_strict {
+        return Err(format!(
+            "{} event layout mismatch(es) with IDL {}",
+            mismatches.len(),
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+fn compare(idl: &Value) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    let by_name = |section: &str, name: &str| {
+        idl.get(section)?
+            .as_array()?
+            .iter()
+            .find(|item| item.get("name").and_then(Value::as_str) == Some(name))
+    };
+
+    for (name, expected) in EVENT_SCHEMAS {
+        let Some(event) = by_name("events", name) else {
+            mismatches.push(format!("{} is not an event in the IDL", name));
+            continue;
+        };
+
+        if let Some(disc) = event.get("discriminator").and_then(Value::as_array) {
+            let disc: Vec<u64> = disc.iter().filter_map(Value::as_u64).collect();
+            let ours: Vec<u64> = event_discriminator(name).iter().map(|b| *b as u64).collect();
+            if disc != ours {
+                mismatches.push(format!("{} discriminator is {:?} in the IDL", name, disc));
+            }
+        }
+
+        let fields = event
+            .get("fields")
+            .or_else(|| by_name("types", name)?.get("type")?.get("fields"))
+            .and_then(Value::as_array);
+        let Some(fields) = fields else {
+            mismatches.push(format!("{} has no field list in the IDL", name));
+            continue;
+        };
+        let fields: Vec<(String, String)> = fields
+            .iter()
+            .map(|field| {
+                let name = field.get("name").and_then(Value::as_str).unwrap_or("?");
+                let ty = match field.get("type") {
+                    Some(Value::String(ty)) => ty.clone(),
+                    Some(other) => other.to_string(),
+                    None => "?".to_string(),
+                };
+                (name.to_string(), ty)
+            })
+            .collect();
+
+        for i in 0..expected.len().max(fields.len()) {
+            match (expected.get(i), fields.get(i)) {
+                (Some((field, ty)), Some((idl_field, idl_ty))) => {
+                    if normalize(field) != normalize(idl_field) || normalize(ty) != normalize(idl_ty) {
+                        mismatches.push(format!(
+                            "{} field {} is {}: {} here, {}: {} in the IDL",
+                            name, i, field, ty, idl_field, idl_ty
+                        ));
+                    }
+                }
+                (Some((field, ty)), None) => {
+                    mismatches.push(format!("{} field {} ({}: {}) is missing in the IDL", name, i, field, ty));
+                }
+                (None, Some((idl_field, idl_ty))) => {
+                    mismatches.push(format!(
+                        "{} has an extra field {} ({}: {}) in the IDL",
+                        name, i, idl_field, idl_ty
+                    ));
+                }
+                (None, None) => {}
+            }
+        }
+    }
+    mismatches
+}
+
+/// `order_id`/`orderId` and `pubkey`/`publicKey` are the same thing.
+fn normalize(s: &str) -> String {
+    s.chars()
+        .filter(|c| *c != '_')
+        .flat_map(char::to_lowercase)
+        .collect::<String>()
+        .replace("publickey", "pubkey")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fields(list: &[(&str, &str)]) -> Value {
+        list.iter()
+            .map(|(name, ty)| json!({ "name": name, "type": ty }))
+            .collect()
+    }
+
+    #[test]
+    fn reads_both_idl_formats() {
+        let current = json!({
+            "events": EVENT_SCHEMAS
+                .iter()
+                .map(|(name, _)| json!({ "name": name, "discriminator": event_discriminator(name) }))
+                .collect::<Vec<_>>(),
+            "types": EVENT_SCHEMAS
+                .iter()
+                .map(|(name, f)| json!({ "name": name, "type": { "kind": "struct", "fields": fields(f) } }))
+                .collect::<Vec<_>>(),
+        });
+        assert_eq!(compare(&current), Vec::<String>::new());
+
+        let legacy = json!({
+            "events": [
+                { "name": "OrderCreated", "fields": fields(&[("order", "publicKey"), ("orderId", "u64"), ("customer", "publicKey"), ("amount", "u64")]) },
+                { "name": "OrderAccepted", "fields": fields(&[("order", "publicKey"), ("courier", "publicKey")]) },
+                { "name": "OrderCompleted", "fields": fields(&[("order", "publicKey"), ("orderId", "u64"), ("courier", "publicKey"), ("amount", "u32"), ("tip", "u64")]) },
+            ],
+        });
+        assert_eq!(
+            compare(&legacy),
+            [
+                "OrderCompleted field 3 is amount: u64 here, amount: u32 in the IDL",
+                "OrderCompleted has an extra field 4 (tip: u64) in the IDL",
+            ]
+        );
+    }
+}
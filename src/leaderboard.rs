@@ -0,0 +1,71 @@
+use crate::{EventRecord, WebEvent};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Mutex};
+
+//
+// ---------------- Courier leaderboard
+//
+// Completed orders and volume per courier since startup, folded from
+// `OrderCompleted` events.
+#[derive(Default)]
+pub struct Leaderboard {
+    couriers: Mutex<HashMap<String, CourierTotals>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub(crate) struct CourierTotals {
+    completed: u64,
+    volume: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LeaderboardEntry {
+    pub courier: String,
+    pub completed: u64,
+    pub volume: u64,
+}
+
+impl Leaderboard {
+    pub fn apply(&self, record: &EventRecord) {
+        if let WebEvent::OrderCompleted {
+            courier, amount, ..
+        } = &record.event
+        {
+            let mut couriers = self.couriers.lock().unwrap();
+            let totals = couriers.entry(courier.clone()).or_default();
+            totals.completed += 1;
+            totals.volume = totals.volume.saturating_add(*amount);
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> HashMap<String, CourierTotals> {
+        self.couriers.lock().unwrap().clone()
+    }
+
+    pub(crate) fn restore(&self, couriers: HashMap<String, CourierTotals>) {
+        *self.couriers.lock().unwrap() = couriers;
+    }
+
+    /// Forgets every courier, for `/admin/stats/reset`.
+    pub fn reset(&self) {
+        self.couriers.lock().unwrap().clear();
+    }
+
+    /// Top `limit` couriers by volume, then completed orders.
+    pub fn top(&self, limit: usize) -> Vec<LeaderboardEntry> {
+        let couriers = self.couriers.lock().unwrap();
+        let mut entries: Vec<LeaderboardEntry> = couriers
+            .iter()
+            .map(|(courier, totals)| LeaderboardEntry {
+                courier: courier.clone(),
+                completed: totals.completed,
+                volume: totals.volume,
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            (b.volume, b.completed, &a.courier).cmp(&(a.volume, a.completed, &b.courier))
+        });
+        entries.truncate(limit);
+        entries
+    }
+}
@@ -0,0 +1,105 @@
+//! Streams Anchor order events from Solana program logs.
+//!
+//! The `block_delivery_service` binary wraps this in an HTTP/WebSocket
+//! server; [`event_stream`] gives the same events to an embedding service
+//! without the server.
+
+use log_style::icon;
+use std::future;
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+use tracing::info;
+
+mod admin;
+mod block_time;
+mod channel;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod config;
+mod deadletter;
+mod debounce;
+mod encode;
+mod events;
+mod finality;
+mod idl;
+mod leaderboard;
+mod listener;
+mod log_style;
+mod metrics;
+mod ndjson;
+mod orders;
+mod otlp;
+mod pause;
+mod pda;
+mod proto;
+mod reorder;
+mod replay;
+mod rollback;
+mod server;
+mod shutdown;
+mod sla;
+mod snapshot;
+mod state;
+mod tick;
+mod tx_index;
+
+pub use config::Config;
+pub use events::{EventKind, EventRecord, EventScheme, WebEvent};
+pub use finality::Finality;
+pub use idl::check_idl;
+pub use log_style::LogStyle;
+pub use pda::SeedTemplate;
+
+use state::AppState;
+
+/// Subscribes to every program in `config.program_ids` and yields decoded
+/// events as they arrive, reconnecting with backoff on its own.
+///
+/// Must be called inside a Tokio runtime. Events a slow consumer falls too far
+/// behind on are skipped. The stream never ends.
+pub fn event_stream(config: Config) -> impl Stream<Item = WebEvent> {
+    log_style::init(config.log_style);
+    let state = AppState::new(&config);
+    let rx = state.channel.tx.subscribe();
+    listener::spawn_listeners(&state);
+
+    BroadcastStream::new(rx).filter_map(|r| r.ok().map(|record| record.event))
+}
+
+/// Runs the full service: listeners, HTTP/WS server on `0.0.0.0:3000` and,
+/// with `--stdout-ndjson` and `UDS_PATH`, the NDJSON sinks. Never returns:
+/// signals, fatal errors and panics all end the process through one
+/// shutdown path that logs the reason.
+pub async fn run(config: Config) {
+    log_style::init(config.log_style);
+    info!(
+        "{} Matching event data prefix {:?}{}",
+        icon("🔎"),
+        config.data_prefix,
+        if config.data_prefix_case_insensitive {
+            " (case-insensitive)"
+        } else {
+            ""
+        }
+    );
+
+    let state = AppState::new(&config);
+    shutdown::install(&state);
+    if state.is_standby() {
+        info!(
+            "{} Starting as standby: listening and buffering, not serving clients until POST /admin/promote",
+            icon("⏸️")
+        );
+    }
+    server::start_server(&state).await;
+
+    if config.stdout_ndjson {
+        ndjson::spawn_stdout_ndjson(&state.channel.tx, state.config.clone());
+    }
+    if let Some(path) = &config.uds_path {
+        ndjson::spawn_uds_ndjson(&state.channel.tx, state.config.clone(), path);
+    }
+
+    listener::spawn_listeners(&state);
+
+    future::pending::<()>().await;
+}
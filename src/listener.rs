@@ -0,0 +1,1310 @@
+use crate::{
+    config::{Config, Source},
+    events::{
+        EventKind, EventRecord, EventScheme, OrderAccepted, OrderAccount, OrderCompleted,
+        OrderCreated, WebEvent,
+    },
+    finality::{Finality, DEDUP_CAPACITY},
+    log_style::icon,
+    metrics::Metrics,
+    orders::{now_secs, OrderStates},
+    state::AppState,
+};
+use anchor_lang::prelude::borsh::BorshDeserialize;
+use base64::{engine::general_purpose, Engine as _};
+use crossbeam_channel::{select, Receiver, Sender};
+use lru::LruCache;
+use solana_client::{
+    pubsub_client::PubsubClient,
+    rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient},
+    rpc_config::{RpcTransactionConfig, RpcTransactionLogsConfig, RpcTransactionLogsFilter},
+    rpc_response::{Response as RpcResponse, RpcLogsResponse, RpcResponseContext},
+};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::{option_serializer::OptionSerializer, UiTransactionEncoding};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    num::NonZeroUsize,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+//
+// ---------------- Solana PubSub listener
+//
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Token bucket behind `MAX_PARSE_RATE`: admits up to `rate` event data
+/// lines per second over all listeners, with a second's worth of burst.
+pub struct ParseLimiter {
+    rate: f64,
+    bucket: Mutex<(f64, Instant)>,
+    /// Set while lines are being dropped, to warn once per episode.
+    shedding: AtomicBool,
+}
+
+impl ParseLimiter {
+    pub fn new(rate: u32) -> Self {
+        let rate = rate as f64;
+        Self {
+            rate,
+            bucket: Mutex::new((rate, Instant::now())),
+            shedding: AtomicBool::new(false),
+        }
+    }
+
+    fn admit(&self) -> bool {
+        let mut bucket = self.bucket.lock().unwrap();
+        let (tokens, refilled_at) = &mut *bucket;
+        let now = Instant::now();
+        *tokens = (*tokens + now.duration_since(*refilled_at).as_secs_f64() * self.rate).min(self.rate);
+        *refilled_at = now;
+        let admitted = *tokens >= 1.0;
+        if admitted {
+            *tokens -= 1.0;
+        }
+        drop(bucket);
+
+        let was_shedding = self.shedding.swap(!admitted, Ordering::Relaxed);
+        // Equal means the state flipped.
+        if was_shedding == admitted {
+            if admitted {
+                info!("{} Back under MAX_PARSE_RATE", icon("✅"));
+            } else {
+                warn!("{} MAX_PARSE_RATE reached, dropping event data", icon("🚫"));
+            }
+        }
+        admitted
+    }
+}
+
+/// Wakes every listener for `POST /admin/resubscribe`.
+#[derive(Default)]
+pub struct Resubscribe {
+    listeners: Mutex<Vec<Sender<()>>>,
+}
+
+impl Resubscribe {
+    fn register(&self) -> Receiver<()> {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        self.listeners.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// Returns the number of listeners signalled.
+    pub fn trigger(&self) -> usize {
+        let listeners = self.listeners.lock().unwrap();
+        for tx in listeners.iter() {
+            // A full slot means a trigger is already pending.
+            let _ = tx.try_send(());
+        }
+        listeners.len()
+    }
+}
+
+/// Caps concurrently open subscriptions at `MAX_SUBSCRIPTIONS`; listeners
+/// beyond it wait until an open subscription ends or rotates out after
+/// `SUBSCRIPTION_SLOT_HOLD_SECS`. Slots go to waiters in arrival order, so a
+/// listener that rotates out queues behind the ones already waiting.
+#[derive(Default)]
+pub struct SubscriptionSlots {
+    max: Option<usize>,
+    state: Mutex<SlotState>,
+    freed: Condvar,
+}
+
+#[derive(Default)]
+struct SlotState {
+    active: usize,
+    waiting: usize,
+    /// Ticket handed to the next listener that queues.
+    next_ticket: u64,
+    /// Ticket of the listener first in line.
+    serving: u64,
+}
+
+/// Holds one slot for the life of a subscription.
+struct Slot<'a>(&'a SubscriptionSlots);
+
+impl SubscriptionSlots {
+    pub fn new(max: Option<usize>) -> Self {
+        Self {
+            max,
+            ..Self::default()
+        }
+    }
+
+    /// Listeners waiting for a slot.
+    pub fn waiting(&self) -> usize {
+        self.state.lock().unwrap().waiting
+    }
+
+    fn acquire(&self) -> Slot<'_> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(max) = self.max {
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+            state.waiting += 1;
+            while state.active >= max || state.serving != ticket {
+                state = self.freed.wait(state).unwrap();
+            }
+            state.waiting -= 1;
+            state.serving += 1;
+            // The next in line may fit as well.
+            self.freed.notify_all();
+        }
+        state.active += 1;
+        Slot(self)
+    }
+
+    /// Whether a subscription that took its slot at `since` should hand it
+    /// to a waiting listener.
+    fn is_due(&self, since: Instant, hold: Duration) -> bool {
+        self.max.is_some() && since.elapsed() >= hold && self.waiting() > 0
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().active -= 1;
+        self.0.freed.notify_all();
+    }
+}
+
+/// Why a subscription's receive loop ended.
+enum End {
+    Closed,
+    Idle,
+    Requested,
+    Rotated,
+}
+
+/// One listener thread per configured program and commitment level, once
+/// the startup health gate (if any) has passed.
+pub fn spawn_listeners(state: &AppState) {
+    let state = state.clone();
+    thread::spawn(move || {
+        if let Some(max_wait) = state.config.startup_health_timeout {
+            wait_for_rpc_health(&state, max_wait);
+        }
+        for program_id in state.config.program_ids.iter().copied() {
+            for finality in state.config.finality_levels(&program_id) {
+                let ws_url = state.config.ws_url.clone();
+                let state = state.clone();
+                thread::spawn(move || match state.config.source {
+                    Source::Subscribe => listen(ws_url, program_id, finality, state),
+                    Source::Poll => poll(program_id, finality, state),
+                });
+            }
+        }
+    });
+}
+
+/// Polls `getHealth` until the node reports healthy or `max_wait` passes;
+/// either way listening starts afterwards.
+fn wait_for_rpc_health(state: &AppState, max_wait: Duration) {
+    let started = Instant::now();
+    let mut last_error = None;
+    while started.elapsed() < max_wait {
+        match state.rpc.get_health() {
+            Ok(()) => {
+                if last_error.is_some() {
+                    info!("{} RPC healthy after {:?}", icon("📡"), started.elapsed());
+                }
+                return;
+            }
+            Err(e) => {
+                if last_error.is_none() {
+                    info!("{} Waiting for RPC to become healthy: {}", icon("⏱️"), e);
+                }
+                last_error = Some(e);
+            }
+        }
+        thread::sleep(state.config.startup_health_poll_interval);
+    }
+    warn!(
+        "{} RPC still unhealthy after {:?} ({}), subscribing anyway",
+        icon("⚠️"),
+        max_wait,
+        last_error.map(|e| e.to_string()).unwrap_or_default()
+    );
+}
+
+fn listen(ws_url: String, program_id: Pubkey, finality: Option<Finality>, state: AppState) {
+    let resubscribe = state.resubscribe.register();
+    let clock = state.metrics.subscription_clock();
+    let mut backoff = INITIAL_BACKOFF;
+    // When the previous subscription ended; `None` before the first.
+    let mut dropped_at: Option<Instant> = None;
+
+    loop {
+        let slot = state.subscription_slots.acquire();
+        let (client, receiver) = match PubsubClient::logs_subscribe(
+            &ws_url,
+            RpcTransactionLogsFilter::Mentions(vec![program_id.to_string()]),
+            RpcTransactionLogsConfig {
+                commitment: finality.map(Finality::commitment),
+            },
+        ) {
+            Ok(sub) => sub,
+            Err(e) => {
+                Metrics::inc(&state.metrics.pubsub_connect_failures_total);
+                warn!(
+                    "{} logs_subscribe to {} failed: {}, retrying in {:?}",
+                    icon("❌"),
+                    ws_url, e, backoff
+                );
+                // Let a waiting listener try while this one backs off.
+                drop(slot);
+                // A manual resubscribe cuts the wait short and starts over.
+                backoff = match resubscribe.recv_timeout(backoff) {
+                    Ok(()) => INITIAL_BACKOFF,
+                    Err(_) => (backoff * 2).min(MAX_BACKOFF),
+                };
+                continue;
+            }
+        };
+
+        backoff = INITIAL_BACKOFF;
+        went_live(&state, &program_id, &clock, dropped_at.take());
+
+        info!(
+            "{} Listening Solana events on {} for program {}{}",
+            icon("📡"),
+            ws_url,
+            program_id,
+            finality.map(|f| format!(" at {:?}", f)).unwrap_or_default()
+        );
+
+        let idle_timeout = state.config.subscription_idle_timeout;
+        let hold = state.config.subscription_slot_hold;
+        let subscribed_at = Instant::now();
+        // Wakes the loop to check whether the slot is wanted elsewhere.
+        let rotate = match state.config.max_subscriptions {
+            Some(_) => crossbeam_channel::tick(hold),
+            None => crossbeam_channel::never(),
+        };
+        let end = loop {
+            let msg = match idle_timeout {
+                Some(timeout) => select! {
+                    recv(receiver) -> msg => msg.map_err(|_| End::Closed),
+                    recv(resubscribe) -> _ => Err(End::Requested),
+                    recv(rotate) -> _ => Err(End::Rotated),
+                    default(timeout) => Err(End::Idle),
+                },
+                None => select! {
+                    recv(receiver) -> msg => msg.map_err(|_| End::Closed),
+                    recv(resubscribe) -> _ => Err(End::Requested),
+                    recv(rotate) -> _ => Err(End::Rotated),
+                },
+            };
+            let msg = match msg {
+                Ok(msg) => msg,
+                Err(End::Rotated) if !state.subscription_slots.is_due(subscribed_at, hold) => continue,
+                Err(end) => break end,
+            };
+            // Failed transactions are rolled back, but their logs still
+            // carry whatever events were emitted before the failure.
+            if state.config.ignore_failed_tx && msg.value.err.is_some() {
+                Metrics::inc(&state.metrics.failed_tx_skipped_total);
+                continue;
+            }
+            process_logs(&msg, &program_id, finality, &state);
+        };
+
+        dropped_at = Some(went_down(&state, &program_id, &clock));
+        drop(slot);
+
+        match end {
+            End::Closed => {
+                warn!("{} Subscription to {} closed, reconnecting", icon("❌"), ws_url);
+            }
+            End::Idle => {
+                Metrics::inc(&state.metrics.subscription_idle_resubscribes_total);
+                warn!(
+                    "{} No messages from {} for {:?}, resubscribing",
+                    icon("⏱️"),
+                    ws_url,
+                    idle_timeout.unwrap_or_default()
+                );
+            }
+            End::Requested => info!("{} Resubscribing to {} on request", icon("🔁"), ws_url),
+            End::Rotated => {
+                Metrics::inc(&state.metrics.subscription_rotations_total);
+                info!(
+                    "{} Handing {}'s subscription slot to a waiting listener after {:?}",
+                    icon("🔁"),
+                    program_id,
+                    hold
+                );
+            }
+        }
+        if !matches!(end, End::Closed) {
+            // Dropping the client unsubscribes, which blocks until its reader
+            // thread sees traffic; don't hold up the new subscription for it.
+            thread::spawn(move || drop(client));
+        }
+    }
+}
+
+/// Records one of `program_id`'s sources coming up, `dropped_at` being when
+/// it last went down.
+fn went_live(state: &AppState, program_id: &Pubkey, clock: &AtomicU64, dropped_at: Option<Instant>) {
+    count_live(state, program_id, true);
+    clock.store(now_secs() as u64, Ordering::Relaxed);
+    if let Some(dropped_at) = dropped_at {
+        Metrics::inc(&state.metrics.reconnect_count);
+        notify_reconnected(state, program_id, dropped_at);
+    }
+}
+
+/// Records one of `program_id`'s sources going down; returns when.
+fn went_down(state: &AppState, program_id: &Pubkey, clock: &AtomicU64) -> Instant {
+    count_live(state, program_id, false);
+    clock.store(0, Ordering::Relaxed);
+    Instant::now()
+}
+
+/// Counts one of `program_id`'s sources going up or down, overall and in
+/// its `/stats/programs` entry.
+fn count_live(state: &AppState, program_id: &Pubkey, up: bool) {
+    let counters = state.program_counters(program_id).map(|c| &c.live_subscriptions);
+    for live in [Some(&*state.live_subscriptions), counters].into_iter().flatten() {
+        if up {
+            live.fetch_add(1, Ordering::Relaxed);
+        } else {
+            live.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// With `NOTIFY_RECONNECT`, tells clients that `program_id`'s events since
+/// `dropped_at` were missed.
+fn notify_reconnected(state: &AppState, program_id: &Pubkey, dropped_at: Instant) {
+    if !state.config.notify_reconnect {
+        return;
+    }
+    let event = WebEvent::SourceReconnected {
+        program_id: program_id.to_string(),
+        gap_ms: dropped_at.elapsed().as_millis() as u64,
+        last_seq: state.replay.last_seq(),
+    };
+    state.publish(
+        Some(program_id),
+        EventRecord {
+            event,
+            slot: None,
+            signature: None,
+            block_time: None,
+            tx_index: None,
+            log_index: None,
+            pda_valid: None,
+            finality: None,
+            raw_base64: None,
+            received_at: None,
+            source: None,
+            seq: 0,
+        },
+    );
+}
+
+//
+// ---------------- RPC polling source (`SOURCE=poll`)
+//
+// For providers without a usable `logsSubscribe`: lists the program's new
+// signatures every `POLL_INTERVAL_MS` and fetches each transaction's logs,
+// feeding them through the same path as subscription notifications. Starts
+// at the newest signature, like a fresh subscription would.
+const POLL_PAGE: usize = 1000;
+
+fn poll(program_id: Pubkey, finality: Option<Finality>, state: AppState) {
+    let resubscribe = state.resubscribe.register();
+    // `getTransaction` doesn't serve `processed`.
+    let commitment = match finality {
+        Some(Finality::Processed) | None => CommitmentConfig::confirmed(),
+        Some(finality) => finality.commitment(),
+    };
+    // `until` can skip a signature RPC nodes disagree on; this catches any
+    // transaction a later page hands out again.
+    let mut seen: LruCache<String, ()> = LruCache::new(NonZeroUsize::new(DEDUP_CAPACITY).unwrap());
+    let mut until: Option<Signature> = None;
+    let mut first_poll = true;
+    let clock = state.metrics.subscription_clock();
+    let mut live = false;
+    let mut dropped_at: Option<Instant> = None;
+    let mut backoff = INITIAL_BACKOFF;
+
+    info!(
+        "{} Polling {} every {:?} for program {}",
+        icon("📡"),
+        state.config.rpc_url,
+        state.config.poll_interval,
+        program_id
+    );
+
+    loop {
+        let page = state.rpc.get_signatures_for_address_with_config(
+            &program_id,
+            GetConfirmedSignaturesForAddress2Config {
+                before: None,
+                until,
+                limit: Some(POLL_PAGE),
+                commitment: Some(commitment),
+            },
+        );
+        let page = match page {
+            Ok(page) => page,
+            Err(e) => {
+                Metrics::inc(&state.metrics.poll_failures_total);
+                if live {
+                    live = false;
+                    dropped_at = Some(went_down(&state, &program_id, &clock));
+                }
+                warn!(
+                    "{} get_signatures_for_address on {} failed: {}, retrying in {:?}",
+                    icon("❌"),
+                    state.config.rpc_url,
+                    e,
+                    backoff
+                );
+                backoff = match resubscribe.recv_timeout(backoff) {
+                    Ok(()) => INITIAL_BACKOFF,
+                    Err(_) => (backoff * 2).min(MAX_BACKOFF),
+                };
+                continue;
+            }
+        };
+
+        backoff = INITIAL_BACKOFF;
+        if !live {
+            live = true;
+            went_live(&state, &program_id, &clock, dropped_at.take());
+        }
+
+        if let Some(newest) = page.first() {
+            until = Signature::from_str(&newest.signature).ok().or(until);
+        }
+        if !std::mem::take(&mut first_poll) {
+            if page.len() == POLL_PAGE {
+                warn!(
+                    "{} {} or more new transactions in one poll, older ones are skipped",
+                    icon("⚠️"),
+                    POLL_PAGE
+                );
+            }
+            // Newest first from RPC; emit in chain order.
+            for status in page.iter().rev() {
+                if seen.put(status.signature.clone(), ()).is_some() {
+                    continue;
+                }
+                if state.config.ignore_failed_tx && status.err.is_some() {
+                    Metrics::inc(&state.metrics.failed_tx_skipped_total);
+                    continue;
+                }
+                let Some(logs) = fetch_logs(&state.rpc, &status.signature, commitment) else {
+                    continue;
+                };
+                let msg = RpcResponse {
+                    context: RpcResponseContext {
+                        slot: status.slot,
+                        api_version: None,
+                    },
+                    value: RpcLogsResponse {
+                        signature: status.signature.clone(),
+                        err: status.err.clone(),
+                        logs,
+                    },
+                };
+                process_logs(&msg, &program_id, finality, &state);
+            }
+        }
+
+        // A manual resubscribe polls right away.
+        let _ = resubscribe.recv_timeout(state.config.poll_interval);
+    }
+}
+
+fn process_logs(
+    msg: &RpcResponse<RpcLogsResponse>,
+    program_id: &Pubkey,
+    finality: Option<Finality>,
+    state: &AppState,
+) {
+    if let (Some(tracker), Some(finality)) = (&state.finality, finality)
+        && tracker.drops_level(finality)
+    {
+        return;
+    }
+    // `DUAL_COMMITMENT` decides per event type, once for the whole notification.
+    let mut decided: HashMap<EventKind, bool> = HashMap::new();
+    let mut admit = |kind| match (&state.finality, finality) {
+        (Some(tracker), Some(finality)) => *decided
+            .entry(kind)
+            .or_insert_with(|| tracker.admit(program_id, &msg.value.signature, kind, finality)),
+        _ => true,
+    };
+
+    let slot = msg.context.slot;
+    // Emitted together once all lines are through, so logs can be held
+    // back for `PARTY_ALLOWLIST` and lookups are shared.
+    let mut records = Vec::new();
+    let mut any_event = false;
+    let record = |event, line: usize, raw: Option<&str>| EventRecord {
+        event,
+        slot: Some(slot),
+        signature: Some(msg.value.signature.clone()),
+        block_time: None,
+        tx_index: None,
+        log_index: state.tx_indexes.as_ref().map(|_| line as u32),
+        pda_valid: None,
+        finality,
+        raw_base64: raw.map(str::to_string),
+        received_at: None,
+        source: None,
+        seq: 0,
+    };
+
+    let truncated = msg.value.logs.iter().any(|log| log == LOG_TRUNCATED);
+    let full_logs = if truncated {
+        Metrics::inc(&state.metrics.truncated_logs_total);
+        let full = state
+            .config
+            .truncated_log_fallback
+            .then(|| fetch_full_logs(&state.rpc, &msg.value.signature))
+            .flatten();
+        match full {
+            Some(_) => Metrics::inc(&state.metrics.truncated_logs_recovered_total),
+            None => warn!(
+                "{} Logs of tx {} truncated, events may be missing",
+                icon("✂️"),
+                msg.value.signature
+            ),
+        }
+        full
+    } else {
+        None
+    };
+    let logs = full_logs.as_ref().unwrap_or(&msg.value.logs);
+    let counters = state.program_counters(program_id);
+
+    for (line, log) in own_logs(logs, program_id) {
+        let started = Instant::now();
+        let matched = match_event(log, &state.config, state.config.event_scheme(program_id));
+        if let Ok(m) = &matched
+            && !admit(m.kind)
+        {
+            continue;
+        }
+        // Only lines that are events of ours count against the rate, not
+        // e.g. the plain-text logs `DECODE_PROGRAM_LOG` looks at.
+        if let (Ok(_), Some(limiter)) = (&matched, &state.parse_limiter)
+            && !limiter.admit()
+        {
+            Metrics::inc(&state.metrics.parse_rate_dropped_total);
+            continue;
+        }
+        let (event, raw) = match matched.and_then(decode_event) {
+            Ok(decoded) => {
+                state.metrics.observe_parse(decoded.0.kind(), started.elapsed());
+                if let Some(c) = counters {
+                    c.matched(decoded.0.kind());
+                }
+                decoded
+            }
+            Err(ParseError::Oversized(bytes)) => {
+                Metrics::inc(&state.metrics.oversized_events_total);
+                warn!(
+                    "{} Skipping ~{} byte event data in tx {} (MAX_EVENT_BYTES {})",
+                    icon("🚫"),
+                    bytes, msg.value.signature, state.config.max_event_bytes
+                );
+                continue;
+            }
+            Err(e @ ParseError::Borsh(..)) => {
+                Metrics::inc(&state.metrics.decode_failures_total);
+                if let Some(c) = counters {
+                    Metrics::inc(&c.decode_failures);
+                }
+                warn!(
+                    "{} Undecodable event data in tx {}: {}",
+                    icon("🧩"),
+                    msg.value.signature,
+                    e
+                );
+                let raw = strip_data_prefix(log, &state.config).unwrap_or(log);
+                state
+                    .dead_letters
+                    .push(&msg.value.signature, slot, raw, e.to_string());
+                continue;
+            }
+            Err(
+                e @ (ParseError::UnknownDiscriminator(_)
+                | ParseError::NotEventData
+                | ParseError::InvalidBase64
+                | ParseError::TooShort(_)),
+            ) => {
+                if let (ParseError::UnknownDiscriminator(_), Some(c)) = (&e, counters) {
+                    Metrics::inc(&c.unknown_discriminator);
+                }
+                // Base64-looking text under `DECODE_PROGRAM_LOG` lands here too.
+                if state.config.emit_program_logs
+                    && let Some(message) = log.strip_prefix(PROGRAM_LOG_PREFIX)
+                    && admit(EventKind::ProgramLog)
+                {
+                    let event = WebEvent::ProgramLog {
+                        signature: msg.value.signature.clone(),
+                        message: message.to_string(),
+                    };
+                    // The signature is in the event already.
+                    records.push(EventRecord { signature: None, ..record(event, line, None) });
+                }
+                continue;
+            }
+            Err(_) => continue,
+        };
+
+        // Before the allowlist, which may need the backfilled customer.
+        if state.config.order_backfill {
+            backfill_order(state, &event);
+        }
+        if let Some(allowed) = &state.config.party_allowlist
+            && !involves_party(&event, allowed, &state.orders)
+        {
+            Metrics::inc(&state.metrics.party_filtered_events_total);
+            continue;
+        }
+
+        let pda_valid = state
+            .config
+            .order_pda_seeds
+            .as_ref()
+            .and_then(|seeds| seeds.verify(&event, program_id));
+        if pda_valid == Some(false) {
+            warn!(
+                "{} Order {} is not the expected PDA (tx {})",
+                icon("⚠️"),
+                event.order(),
+                msg.value.signature
+            );
+        }
+
+        any_event = true;
+        records.push(EventRecord { pda_valid, ..record(event, line, Some(raw)) });
+    }
+
+    // Logs of a transaction none of whose events passed would show what
+    // the allowlist hides.
+    if !any_event && state.config.party_allowlist.is_some() {
+        return;
+    }
+    if records.is_empty() {
+        return;
+    }
+    let block_time = state
+        .block_times
+        .as_ref()
+        .and_then(|cache| cache.get(slot, &state.metrics));
+    let tx_index = state
+        .tx_indexes
+        .as_ref()
+        .and_then(|cache| cache.get(slot, &msg.value.signature, &state.metrics));
+    for record in records {
+        state.emit(program_id, EventRecord { block_time, tx_index, ..record });
+    }
+}
+
+/// Whether the event's customer or courier is in `allowed`. Events without a
+/// customer fall back to the one tracked from the order's `OrderCreated`.
+pub fn involves_party(event: &WebEvent, allowed: &HashSet<String>, orders: &OrderStates) -> bool {
+    event
+        .customer()
+        .into_iter()
+        .chain(event.courier())
+        .any(|party| allowed.contains(party))
+        || event.customer().is_none()
+            && orders
+                .customer(event.order())
+                .is_some_and(|customer| allowed.contains(&customer))
+}
+
+/// The lines logged by `program_id` itself. A `Mentions` subscription also
+/// delivers transactions where the program is reached via CPI (e.g. from a
+/// router), and the same logs then carry data lines of the outer program and
+/// of anything else invoked. The runtime brackets every invocation with
+/// `Program <id> invoke [n]` and `Program <id> success|failed`, so a stack of
+/// those attributes each line to the innermost running program. Lines outside
+/// any invocation are kept, for logs that lack the markers.
+fn own_logs<'a>(logs: &'a [String], program_id: &Pubkey) -> Vec<(usize, &'a str)> {
+    let program_id = program_id.to_string();
+    let mut stack: Vec<&str> = Vec::new();
+    let mut own = Vec::new();
+
+    for (i, log) in logs.iter().enumerate() {
+        // `Program log: …`, `Program data: …` etc. have a colon after the
+        // first word; program ids never do.
+        if let Some((program, tail)) = log
+            .strip_prefix("Program ")
+            .and_then(|rest| rest.split_once(' '))
+            .filter(|(program, _)| !program.ends_with(':'))
+        {
+            if tail.starts_with("invoke [") {
+                stack.push(program);
+                continue;
+            }
+            if tail == "success" || tail.starts_with("failed") {
+                stack.pop();
+                continue;
+            }
+        }
+        if stack.last().is_none_or(|top| *top == program_id) {
+            own.push((i, log.as_str()));
+        }
+    }
+    own
+}
+
+/// `ORDER_BACKFILL`: reads the creation details of an order first seen
+/// accepted or completed from its account. A completion that closed the
+/// account leaves nothing to read.
+fn backfill_order(state: &AppState, event: &WebEvent) {
+    if !matches!(event, WebEvent::OrderAccepted { .. } | WebEvent::OrderCompleted { .. })
+        || state.orders.customer(event.order()).is_some()
+    {
+        return;
+    }
+    let account = Pubkey::from_str(event.order())
+        .ok()
+        .and_then(|address| {
+            state
+                .rpc
+                .get_account_data(&address)
+                .map_err(|e| warn!("get_account {} failed: {}", address, e))
+                .ok()
+        })
+        .and_then(|data| OrderAccount::decode(&data));
+    let Some(account) = account else {
+        Metrics::inc(&state.metrics.order_backfill_failures_total);
+        return;
+    };
+    Metrics::inc(&state.metrics.order_backfills_total);
+    let customer = account.customer.to_string();
+    if state.orders.backfill(event.order(), account.order_id, customer, account.amount) {
+        Metrics::inc(&state.metrics.order_states_evicted_total);
+    }
+}
+
+/// Marker the runtime appends once a transaction exceeds its log size limit.
+const LOG_TRUNCATED: &str = "Log truncated";
+
+/// Transaction logs as stored by the RPC node, if they are not truncated too.
+fn fetch_full_logs(rpc: &RpcClient, signature: &str) -> Option<Vec<String>> {
+    let logs = fetch_logs(rpc, signature, CommitmentConfig::confirmed())?;
+    (!logs.iter().any(|log| log == LOG_TRUNCATED)).then_some(logs)
+}
+
+fn fetch_logs(rpc: &RpcClient, signature: &str, commitment: CommitmentConfig) -> Option<Vec<String>> {
+    let signature = Signature::from_str(signature).ok()?;
+    let tx = rpc
+        .get_transaction_with_config(
+            &signature,
+            RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::Json),
+                commitment: Some(commitment),
+                max_supported_transaction_version: Some(0),
+            },
+        )
+        .map_err(|e| warn!("get_transaction {} failed: {}", signature, e))
+        .ok()?;
+
+    match tx.transaction.meta?.log_messages {
+        OptionSerializer::Some(logs) => Some(logs),
+        _ => None,
+    }
+}
+
+/// Prefix of `msg!` output, where some programs (and older Anchor versions)
+/// log their base64 event data.
+const PROGRAM_LOG_PREFIX: &str = "Program log: ";
+
+pub fn strip_data_prefix<'a>(log: &'a str, config: &Config) -> Option<&'a str> {
+    strip_configured_prefix(log, config).or_else(|| {
+        config
+            .decode_program_log
+            .then(|| log.strip_prefix(PROGRAM_LOG_PREFIX))
+            .flatten()
+    })
+}
+
+fn strip_configured_prefix<'a>(log: &'a str, config: &Config) -> Option<&'a str> {
+    let prefix = config.data_prefix.as_str();
+
+    if !config.data_prefix_case_insensitive {
+        return log.strip_prefix(prefix);
+    }
+
+    let head = log.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix).then(|| &log[prefix.len()..])
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    /// Not an event data line at all.
+    NotEventData,
+    /// Decoded size would exceed `MAX_EVENT_BYTES`; rejected before decoding.
+    Oversized(usize),
+    InvalidBase64,
+    /// Fewer than the 8 discriminator bytes.
+    TooShort(usize),
+    /// A known event type that `ENABLED_EVENTS` leaves out.
+    Disabled(EventKind),
+    UnknownDiscriminator([u8; 8]),
+    /// The discriminator matched but the payload didn't deserialize.
+    Borsh(EventKind, String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::NotEventData => write!(f, "not an event data line"),
+            ParseError::Oversized(bytes) => {
+                write!(f, "~{} bytes exceeds MAX_EVENT_BYTES", bytes)
+            }
+            ParseError::InvalidBase64 => write!(f, "invalid base64"),
+            ParseError::TooShort(len) => {
+                write!(f, "{} bytes is too short for a discriminator", len)
+            }
+            ParseError::Disabled(kind) => write!(f, "{:?} is not in ENABLED_EVENTS", kind),
+            ParseError::UnknownDiscriminator(disc) => {
+                write!(f, "unknown discriminator {}", hex(disc))
+            }
+            ParseError::Borsh(kind, e) => write!(f, "{:?} failed to deserialize: {}", kind, e),
+        }
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decodes one log line; also returns its base64 payload.
+pub fn parse_log<'a>(
+    log: &'a str,
+    config: &Config,
+    scheme: &EventScheme,
+) -> Result<(WebEvent, &'a str), ParseError> {
+    match_event(log, config, scheme).and_then(decode_event)
+}
+
+/// An event data line whose discriminator is a known, enabled event type.
+pub struct Matched<'a> {
+    kind: EventKind,
+    bytes: Vec<u8>,
+    base64: &'a str,
+}
+
+/// The cheap half of `parse_log`: finds the event type of a line, up to
+/// deserializing its payload.
+pub fn match_event<'a>(log: &'a str, config: &Config, scheme: &EventScheme) -> Result<Matched<'a>, ParseError> {
+    let base64_data =
+        strip_data_prefix(log, config).ok_or(ParseError::NotEventData)?;
+
+    let decoded_len = base64_data.len() / 4 * 3;
+    if decoded_len > config.max_event_bytes {
+        return Err(ParseError::Oversized(decoded_len));
+    }
+
+    let bytes = general_purpose::STANDARD
+        .decode(base64_data)
+        .map_err(|_| ParseError::InvalidBase64)?;
+
+    if bytes.len() < 8 {
+        return Err(ParseError::TooShort(bytes.len()));
+    }
+
+    let disc = &bytes[..8];
+    let kind = scheme
+        .kind(disc)
+        .ok_or_else(|| ParseError::UnknownDiscriminator(disc.try_into().unwrap()))?;
+    if !config.enabled_events.contains(&kind) {
+        return Err(ParseError::Disabled(kind));
+    }
+    Ok(Matched {
+        kind,
+        bytes,
+        base64: base64_data,
+    })
+}
+
+/// The other half of `parse_log`: deserializes a matched payload.
+pub fn decode_event(matched: Matched<'_>) -> Result<(WebEvent, &str), ParseError> {
+    let Matched { kind, bytes, base64 } = matched;
+    let data = &bytes[8..];
+    let borsh = |e: std::io::Error| ParseError::Borsh(kind, e.to_string());
+
+    let event = match kind {
+        EventKind::OrderCreated => {
+            let e = OrderCreated::try_from_slice(data).map_err(borsh)?;
+            WebEvent::OrderCreated {
+                order: e.order.to_string(),
+                order_id: e.order_id,
+                customer: e.customer.to_string(),
+                amount: e.amount,
+            }
+        }
+        EventKind::OrderAccepted => {
+            let e = OrderAccepted::try_from_slice(data).map_err(borsh)?;
+            WebEvent::OrderAccepted {
+                order: e.order.to_string(),
+                courier: e.courier.to_string(),
+            }
+        }
+        EventKind::OrderCompleted => {
+            let e = OrderCompleted::try_from_slice(data).map_err(borsh)?;
+            WebEvent::OrderCompleted {
+                order: e.order.to_string(),
+                order_id: e.order_id,
+                courier: e.courier.to_string(),
+                amount: e.amount,
+            }
+        }
+        _ => unreachable!("EventScheme only knows program events"),
+    };
+
+    Ok((event, base64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::event_discriminator;
+
+    use anchor_lang::prelude::borsh::to_vec;
+
+    const PROGRAM: &str = "AdScDF7jTLCmb3iP4ZPugb6kxDtix1U7pVRu99VDJwdy";
+
+    fn order_created_base64() -> (String, WebEvent) {
+        let created = OrderCreated {
+            order: anchor_lang::prelude::Pubkey::new_unique(),
+            order_id: 7,
+            customer: anchor_lang::prelude::Pubkey::new_unique(),
+            amount: 100,
+        };
+        let mut bytes = event_discriminator("OrderCreated").to_vec();
+        bytes.extend(to_vec(&created).unwrap());
+        let event = WebEvent::OrderCreated {
+            order: created.order.to_string(),
+            order_id: 7,
+            customer: created.customer.to_string(),
+            amount: 100,
+        };
+        (general_purpose::STANDARD.encode(bytes), event)
+    }
+
+    #[test]
+    fn decodes_program_data_lines() {
+        let (data, event) = order_created_base64();
+        let config = Config::from_pairs(&[]);
+        let line = format!("Program data: {}", data);
+        let (decoded, raw) = parse_log(&line, &config, EventScheme::anchor()).unwrap();
+        assert_eq!((decoded, raw), (event, data.as_str()));
+    }
+
+    #[test]
+    fn party_allowlist_matches_customer_or_courier() {
+        let allowed: HashSet<String> = ["alice".to_string()].into();
+        let orders = OrderStates::default();
+        let created = |customer: &str| WebEvent::OrderCreated {
+            order: format!("{}-order", customer),
+            order_id: 1,
+            customer: customer.to_string(),
+            amount: 1,
+        };
+        let accepted = |order: &str, courier: &str| WebEvent::OrderAccepted {
+            order: order.to_string(),
+            courier: courier.to_string(),
+        };
+        assert!(involves_party(&created("alice"), &allowed, &orders));
+        assert!(!involves_party(&created("bob"), &allowed, &orders));
+        assert!(involves_party(&accepted("bob-order", "alice"), &allowed, &orders));
+        assert!(!involves_party(&accepted("alice-order", "carol"), &allowed, &orders));
+
+        // Once the creation is tracked, the customer counts for later events.
+        orders.apply(&EventRecord::test(created("alice")));
+        assert!(involves_party(&accepted("alice-order", "carol"), &allowed, &orders));
+    }
+
+    #[test]
+    fn parse_limiter_allows_one_second_of_burst() {
+        let limiter = ParseLimiter::new(3);
+        assert_eq!([(); 4].map(|_| limiter.admit()), [true, true, true, false]);
+        thread::sleep(Duration::from_millis(400));
+        assert!(limiter.admit());
+        assert!(!limiter.admit());
+    }
+
+    #[test]
+    fn decodes_with_each_programs_event_scheme() {
+        let (data, event) = order_created_base64();
+        let config = Config::from_pairs(&[
+            ("PROGRAM_ID", PROGRAM),
+            ("PROGRAM_EVENT_SCHEME", &format!("{}=fork:Created/Accepted/Completed", PROGRAM)),
+        ]);
+        let scheme = config.event_scheme(&PROGRAM.parse().unwrap());
+        assert_eq!(scheme.names[0], "Created");
+
+        let anchor_line = format!("Program data: {}", data);
+        assert!(matches!(
+            parse_log(&anchor_line, &config, scheme),
+            Err(ParseError::UnknownDiscriminator(_))
+        ));
+
+        let mut bytes = general_purpose::STANDARD.decode(&data).unwrap();
+        bytes[..8].copy_from_slice(&scheme.discriminator(EventKind::OrderCreated).unwrap());
+        let fork_line = format!("Program data: {}", general_purpose::STANDARD.encode(bytes));
+        assert_eq!(parse_log(&fork_line, &config, scheme).unwrap().0, event);
+        assert!(parse_log(&fork_line, &config, EventScheme::anchor()).is_err());
+    }
+
+    #[test]
+    fn decodes_program_log_lines_only_when_enabled() {
+        let (data, event) = order_created_base64();
+        let line = format!("Program log: {}", data);
+
+        let config = Config::from_pairs(&[]);
+        assert!(matches!(parse_log(&line, &config, EventScheme::anchor()), Err(ParseError::NotEventData)));
+
+        let config = Config::from_pairs(&[("DECODE_PROGRAM_LOG", "true")]);
+        assert_eq!(parse_log(&line, &config, EventScheme::anchor()).unwrap().0, event);
+        // Ordinary `msg!` output is still skipped.
+        assert!(matches!(
+            parse_log("Program log: Instruction: CreateOrder", &config, EventScheme::anchor()),
+            Err(ParseError::InvalidBase64)
+        ));
+        let data_line = format!("Program data: {}", data);
+        assert_eq!(parse_log(&data_line, &config, EventScheme::anchor()).unwrap().0, event);
+    }
+
+    #[test]
+    fn forwards_program_log_lines_unless_the_allowlist_hides_the_tx() {
+        let (data, event) = order_created_base64();
+        let msg = RpcResponse {
+            context: RpcResponseContext {
+                slot: 1200,
+                api_version: None,
+            },
+            value: RpcLogsResponse {
+                signature: "sig".to_string(),
+                err: None,
+                logs: vec![
+                    format!("Program {} invoke [1]", PROGRAM),
+                    "Program log: Instruction: CreateOrder".to_string(),
+                    format!("Program data: {}", data),
+                    format!("Program {} success", PROGRAM),
+                ],
+            },
+        };
+        let program_id = PROGRAM.parse().unwrap();
+
+        let config = Config::from_pairs(&[("PROGRAM_ID", PROGRAM), ("EMIT_PROGRAM_LOGS", "true")]);
+        let state = AppState::new(&config);
+        let mut rx = state.channel.tx.subscribe();
+        process_logs(&msg, &program_id, None, &state);
+        let log = rx.try_recv().unwrap();
+        assert_eq!(
+            log.event,
+            WebEvent::ProgramLog {
+                signature: "sig".to_string(),
+                message: "Instruction: CreateOrder".to_string(),
+            }
+        );
+        assert_eq!((log.slot, log.signature), (Some(1200), None));
+        assert_eq!(rx.try_recv().unwrap().event, event);
+
+        let stranger = Pubkey::new_unique().to_string();
+        let config = Config::from_pairs(&[
+            ("PROGRAM_ID", PROGRAM),
+            ("EMIT_PROGRAM_LOGS", "true"),
+            ("PARTY_ALLOWLIST", &stranger),
+        ]);
+        let state = AppState::new(&config);
+        let mut rx = state.channel.tx.subscribe();
+        process_logs(&msg, &program_id, None, &state);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn confirmed_only_lets_through_event_types_the_confirmed_copy_missed() {
+        let (created, created_event) = order_created_base64();
+        let (order, courier) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut bytes = event_discriminator("OrderAccepted").to_vec();
+        bytes.extend(order.to_bytes().into_iter().chain(courier.to_bytes()));
+        let accepted = general_purpose::STANDARD.encode(bytes);
+        let notification = |data: &[&str]| RpcResponse {
+            context: RpcResponseContext {
+                slot: 1200,
+                api_version: None,
+            },
+            value: RpcLogsResponse {
+                signature: "sig".to_string(),
+                err: None,
+                logs: std::iter::once(format!("Program {} invoke [1]", PROGRAM))
+                    .chain(data.iter().map(|data| format!("Program data: {}", data)))
+                    .chain([format!("Program {} success", PROGRAM)])
+                    .collect(),
+            },
+        };
+        let program_id = PROGRAM.parse().unwrap();
+        let config = Config::from_pairs(&[
+            ("PROGRAM_ID", PROGRAM),
+            ("DUAL_COMMITMENT", "true"),
+            ("DUAL_COMMITMENT_POLICY", "confirmed_only"),
+        ]);
+        let state = AppState::new(&config);
+        let mut rx = state.channel.tx.subscribe();
+
+        // Two events of one type in a notification get the same answer.
+        let confirmed = notification(&[&created, &created]);
+        process_logs(&confirmed, &program_id, Some(Finality::Confirmed), &state);
+        assert_eq!(rx.try_recv().unwrap().event, created_event);
+        assert_eq!(rx.try_recv().unwrap().event, created_event);
+        assert!(rx.try_recv().is_err());
+
+        let finalized = notification(&[&created, &created, &accepted]);
+        process_logs(&finalized, &program_id, Some(Finality::Finalized), &state);
+        let late = rx.try_recv().unwrap();
+        assert_eq!(
+            late.event,
+            WebEvent::OrderAccepted {
+                order: order.to_string(),
+                courier: courier.to_string(),
+            }
+        );
+        assert_eq!(late.finality, Some(Finality::Finalized));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn parse_rate_is_spent_only_on_event_lines() {
+        let (data, event) = order_created_base64();
+        let mut logs = vec![format!("Program {} invoke [1]", PROGRAM)];
+        logs.extend((0..5).map(|i| format!("Program log: step {}", i)));
+        logs.push(format!("Program data: {}", data));
+        logs.push(format!("Program {} success", PROGRAM));
+        let msg = RpcResponse {
+            context: RpcResponseContext {
+                slot: 1200,
+                api_version: None,
+            },
+            value: RpcLogsResponse {
+                signature: "sig".to_string(),
+                err: None,
+                logs,
+            },
+        };
+        let program_id = PROGRAM.parse().unwrap();
+
+        // One token a second: the plain `msg!` lines must not use it up.
+        let config = Config::from_pairs(&[
+            ("PROGRAM_ID", PROGRAM),
+            ("DECODE_PROGRAM_LOG", "true"),
+            ("MAX_PARSE_RATE", "1"),
+        ]);
+        let state = AppState::new(&config);
+        let mut rx = state.channel.tx.subscribe();
+        process_logs(&msg, &program_id, None, &state);
+        assert_eq!(rx.try_recv().unwrap().event, event);
+        assert_eq!(state.metrics.parse_rate_dropped_total.load(Ordering::Relaxed), 0);
+    }
+
+    const ROUTER: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+
+    #[test]
+    fn own_logs_follow_cpi_into_the_program() {
+        // A router calls our program, which calls the token program.
+        let logs: Vec<String> = [
+            format!("Program {} invoke [1]", ROUTER),
+            "Program log: Instruction: Route".to_string(),
+            "Program data: cm91dGVyIGJlZm9yZQ==".to_string(),
+            format!("Program {} invoke [2]", PROGRAM),
+            "Program log: Instruction: CreateOrder".to_string(),
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA invoke [3]".to_string(),
+            "Program data: dG9rZW4gZGF0YQ==".to_string(),
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA success".to_string(),
+            "Program data: b3VyIGV2ZW50".to_string(),
+            format!("Program {} consumed 12000 of 180000 compute units", PROGRAM),
+            format!("Program {} success", PROGRAM),
+            "Program data: cm91dGVyIGFmdGVy".to_string(),
+            format!("Program {} success", ROUTER),
+        ]
+        .into();
+
+        let own = own_logs(&logs, &PROGRAM.parse().unwrap());
+        let data: Vec<(usize, &str)> = own
+            .iter()
+            .copied()
+            .filter(|(_, log)| log.starts_with("Program data: "))
+            .collect();
+        assert_eq!(data, [(8, "Program data: b3VyIGV2ZW50")]);
+        assert!(own.contains(&(4, "Program log: Instruction: CreateOrder")));
+    }
+
+    #[test]
+    fn own_logs_keep_lines_without_invocation_markers() {
+        let logs = vec!["Program data: b3VyIGV2ZW50".to_string()];
+        assert_eq!(own_logs(&logs, &PROGRAM.parse().unwrap()), [(0, "Program data: b3VyIGV2ZW50")]);
+    }
+
+    #[test]
+    fn a_rotated_out_listener_queues_behind_waiting_ones() {
+        let slots = SubscriptionSlots::new(Some(1));
+        let order = Mutex::new(Vec::new());
+        thread::scope(|s| {
+            let first = slots.acquire();
+            s.spawn(|| {
+                let _slot = slots.acquire();
+                order.lock().unwrap().push("waiter");
+            });
+            while slots.waiting() == 0 {
+                thread::yield_now();
+            }
+            assert!(slots.is_due(Instant::now() - Duration::from_secs(2), Duration::from_secs(1)));
+            drop(first);
+            let _again = slots.acquire();
+            order.lock().unwrap().push("rotated");
+        });
+        assert_eq!(*order.lock().unwrap(), ["waiter", "rotated"]);
+        assert!(!slots.is_due(Instant::now() - Duration::from_secs(2), Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn uptime_follows_the_listeners_still_live() {
+        let state = AppState::new(&Config::from_pairs(&[]));
+        let program_id = state.config.program_ids[0];
+        let (first, second) = (state.metrics.subscription_clock(), state.metrics.subscription_clock());
+        went_live(&state, &program_id, &first, None);
+        went_live(&state, &program_id, &second, None);
+        first.fetch_sub(100, Ordering::Relaxed);
+        second.fetch_sub(10, Ordering::Relaxed);
+        assert!((10..100).contains(&state.metrics.subscription_uptime_seconds()));
+
+        let dropped_at = went_down(&state, &program_id, &second);
+        assert!(state.metrics.subscription_uptime_seconds() >= 100);
+        assert_eq!(state.live_subscriptions.load(Ordering::Relaxed), 1);
+
+        went_live(&state, &program_id, &second, Some(dropped_at));
+        assert!(state.metrics.subscription_uptime_seconds() < 10);
+        assert_eq!(state.metrics.reconnect_count.load(Ordering::Relaxed), 1);
+
+        went_down(&state, &program_id, &first);
+        went_down(&state, &program_id, &second);
+        assert_eq!(state.metrics.subscription_uptime_seconds(), 0);
+    }
+}
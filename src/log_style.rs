@@ -0,0 +1,63 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+//
+// ---------------- Log line markers (`LOG_STYLE`)
+//
+// Log messages start with an emoji marker; `LOG_STYLE=plain` swaps each for
+// an ASCII tag for pipelines that mangle non-ASCII output. Process-wide, as
+// it only affects formatting.
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogStyle {
+    Emoji,
+    Plain,
+}
+
+impl std::str::FromStr for LogStyle {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "emoji" => Ok(LogStyle::Emoji),
+            "plain" => Ok(LogStyle::Plain),
+            _ => Err(()),
+        }
+    }
+}
+
+pub fn init(style: LogStyle) {
+    PLAIN.store(style == LogStyle::Plain, Ordering::Relaxed);
+}
+
+/// `emoji`, or its ASCII tag in plain mode.
+pub fn icon(emoji: &'static str) -> &'static str {
+    if !PLAIN.load(Ordering::Relaxed) {
+        return emoji;
+    }
+    match emoji {
+        "📡" => "[listen]",
+        "🚀" => "[serve]",
+        "🔎" => "[config]",
+        "🌐" => "[client]",
+        "👋" => "[close]",
+        "❌" => "[error]",
+        "🚫" => "[reject]",
+        "⚠️" => "[warn]",
+        "⏱️" => "[idle]",
+        "✂️" => "[truncated]",
+        "🧩" => "[undecodable]",
+        "⏸️" => "[pause]",
+        "▶️" => "[resume]",
+        "💾" => "[snapshot]",
+        "🧹" => "[reset]",
+        "🔁" => "[resubscribe]",
+        "📉" | "📈" => "[sla]",
+        "👑" => "[promote]",
+        "🛑" => "[shutdown]",
+        "⏪" => "[reverted]",
+        _ => "[-]",
+    }
+}
@@ -0,0 +1,443 @@
+use crate::{orders::now_secs, EventKind};
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::Duration,
+};
+
+//
+// ---------------- Prometheus metrics
+//
+// Plain atomics rendered in the Prometheus text exposition format.
+#[derive(Default)]
+pub struct Metrics {
+    pub pubsub_connect_failures_total: AtomicU64,
+    pub poll_failures_total: AtomicU64,
+    pub subscription_idle_resubscribes_total: AtomicU64,
+    pub subscription_rotations_total: AtomicU64,
+    pub block_time_cache_hits_total: AtomicU64,
+    pub block_time_cache_misses_total: AtomicU64,
+    pub tx_index_lookup_failures_total: AtomicU64,
+    pub reverted_events_total: AtomicU64,
+    pub order_states_evicted_total: AtomicU64,
+    pub order_backfills_total: AtomicU64,
+    pub order_backfill_failures_total: AtomicU64,
+    pub rollback_check_failures_total: AtomicU64,
+    pub truncated_logs_total: AtomicU64,
+    pub oversized_events_total: AtomicU64,
+    pub truncated_logs_recovered_total: AtomicU64,
+    pub paused_events_dropped_total: AtomicU64,
+    pub decode_failures_total: AtomicU64,
+    pub failed_tx_skipped_total: AtomicU64,
+    pub debounced_events_total: AtomicU64,
+    pub parse_rate_dropped_total: AtomicU64,
+    pub party_filtered_events_total: AtomicU64,
+    pub encode_failures_total: AtomicU64,
+    pub throttled_events_total: AtomicU64,
+    pub ingested_events_total: AtomicU64,
+    pub ingest_rejected_lines_total: AtomicU64,
+    /// Gauge, maintained by `ClientGuard`.
+    pub connected_clients: AtomicU64,
+    /// Successful subscribes after a program's first one.
+    pub reconnect_count: AtomicU64,
+    /// Unix seconds each listener last subscribed at; 0 while it is down.
+    subscribed_at: Mutex<Vec<Arc<AtomicU64>>>,
+    /// `parse_log` duration of successfully decoded events, by `EventKind::code`.
+    pub parse_seconds: [Histogram; EventKind::ALL.len()],
+    /// Unix seconds of the latest `reset`; 0 if never reset.
+    pub reset_at: AtomicU64,
+    /// Set by `shutdown::exit`, for the final scrape and OTLP push.
+    pub shutdown_reason: OnceLock<&'static str>,
+}
+
+impl Metrics {
+    pub fn inc(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn observe_parse(&self, kind: EventKind, elapsed: Duration) {
+        self.parse_seconds[kind.code() as usize].observe(elapsed);
+    }
+
+    /// Zeroes the counters and histograms for `POST /admin/stats/reset`.
+    /// Gauges describe current state and are left alone.
+    pub fn reset(&self) {
+        for counter in [
+            &self.pubsub_connect_failures_total,
+            &self.poll_failures_total,
+            &self.subscription_idle_resubscribes_total,
+            &self.subscription_rotations_total,
+            &self.block_time_cache_hits_total,
+            &self.block_time_cache_misses_total,
+            &self.tx_index_lookup_failures_total,
+            &self.reverted_events_total,
+            &self.order_states_evicted_total,
+            &self.order_backfills_total,
+            &self.order_backfill_failures_total,
+            &self.rollback_check_failures_total,
+            &self.truncated_logs_total,
+            &self.oversized_events_total,
+            &self.truncated_logs_recovered_total,
+            &self.paused_events_dropped_total,
+            &self.decode_failures_total,
+            &self.failed_tx_skipped_total,
+            &self.debounced_events_total,
+            &self.parse_rate_dropped_total,
+            &self.party_filtered_events_total,
+            &self.encode_failures_total,
+            &self.throttled_events_total,
+            &self.ingested_events_total,
+            &self.ingest_rejected_lines_total,
+            &self.reconnect_count,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        for histogram in &self.parse_seconds {
+            histogram.reset();
+        }
+        self.reset_at.store(now_secs() as u64, Ordering::Relaxed);
+    }
+
+    /// A new listener's subscribe time, counted in `subscription_uptime_seconds`.
+    pub fn subscription_clock(&self) -> Arc<AtomicU64> {
+        let clock = Arc::new(AtomicU64::new(0));
+        self.subscribed_at.lock().unwrap().push(clock.clone());
+        clock
+    }
+
+    /// Time since the latest subscribe among the listeners that are up; 0
+    /// while none is.
+    pub fn subscription_uptime_seconds(&self) -> u64 {
+        let clocks = self.subscribed_at.lock().unwrap();
+        match clocks.iter().map(|c| c.load(Ordering::Relaxed)).max().unwrap_or(0) {
+            0 => 0,
+            since => (now_secs() as u64).saturating_sub(since),
+        }
+    }
+
+    /// Every counter and gauge with its current value; the one list behind
+    /// both `/metrics` and the OTLP export. `broadcast_lag` is sampled by the
+    /// caller, which owns the channels.
+    pub fn samples(&self, broadcast_lag: Duration) -> Vec<Sample> {
+        let load = |value: &AtomicU64| value.load(Ordering::Relaxed) as f64;
+        let counter = |name, help, value| Sample {
+            name,
+            help,
+            kind: Kind::Counter,
+            value: load(value),
+        };
+        let gauge = |name, help, value| Sample {
+            name,
+            help,
+            kind: Kind::Gauge,
+            value,
+        };
+        vec![
+            counter(
+                "pubsub_connect_failures_total",
+                "Failed attempts to open the PubSub logs subscription.",
+                &self.pubsub_connect_failures_total,
+            ),
+            counter(
+                "poll_failures_total",
+                "Failed getSignaturesForAddress polls with SOURCE=poll.",
+                &self.poll_failures_total,
+            ),
+            counter(
+                "subscription_idle_resubscribes_total",
+                "Subscriptions torn down after SUBSCRIPTION_IDLE_TIMEOUT_SECS without messages.",
+                &self.subscription_idle_resubscribes_total,
+            ),
+            counter(
+                "subscription_rotations_total",
+                "Subscriptions that gave up their MAX_SUBSCRIPTIONS slot to a waiting listener.",
+                &self.subscription_rotations_total,
+            ),
+            counter(
+                "block_time_cache_hits_total",
+                "Block time lookups served from the per-slot cache.",
+                &self.block_time_cache_hits_total,
+            ),
+            counter(
+                "block_time_cache_misses_total",
+                "Block time lookups that went to RPC.",
+                &self.block_time_cache_misses_total,
+            ),
+            counter(
+                "tx_index_lookup_failures_total",
+                "Blocks that could not be fetched for ORDERING_METADATA; their events lack tx_index.",
+                &self.tx_index_lookup_failures_total,
+            ),
+            counter(
+                "reverted_events_total",
+                "Reverted events published for transactions in abandoned slots (ROLLBACK_DETECTION).",
+                &self.reverted_events_total,
+            ),
+            counter(
+                "order_states_evicted_total",
+                "Open orders evicted from the order-state projection at ORDER_STATE_CAPACITY.",
+                &self.order_states_evicted_total,
+            ),
+            counter(
+                "order_backfills_total",
+                "Orders first seen accepted or completed whose creation details were read from their account (ORDER_BACKFILL).",
+                &self.order_backfills_total,
+            ),
+            counter(
+                "order_backfill_failures_total",
+                "Order account lookups for ORDER_BACKFILL that failed or found no Order account.",
+                &self.order_backfill_failures_total,
+            ),
+            counter(
+                "rollback_check_failures_total",
+                "Finalized slot lookups for ROLLBACK_DETECTION that failed; retried on the next round.",
+                &self.rollback_check_failures_total,
+            ),
+            counter(
+                "truncated_logs_total",
+                "Log notifications containing the \"Log truncated\" marker.",
+                &self.truncated_logs_total,
+            ),
+            counter(
+                "oversized_events_total",
+                "Event data lines rejected for exceeding MAX_EVENT_BYTES.",
+                &self.oversized_events_total,
+            ),
+            counter(
+                "truncated_logs_recovered_total",
+                "Truncated notifications replaced by complete logs from get_transaction.",
+                &self.truncated_logs_recovered_total,
+            ),
+            counter(
+                "paused_events_dropped_total",
+                "Events discarded while paused because PAUSE_BUFFER_SIZE was full.",
+                &self.paused_events_dropped_total,
+            ),
+            counter(
+                "decode_failures_total",
+                "Event data with a known discriminator that failed to deserialize.",
+                &self.decode_failures_total,
+            ),
+            counter(
+                "failed_tx_skipped_total",
+                "Notifications of failed transactions skipped under IGNORE_FAILED_TX.",
+                &self.failed_tx_skipped_total,
+            ),
+            counter(
+                "debounced_events_total",
+                "Events not broadcast because a later event of the same order superseded them.",
+                &self.debounced_events_total,
+            ),
+            counter(
+                "parse_rate_dropped_total",
+                "Event data lines dropped undecoded because MAX_PARSE_RATE was reached.",
+                &self.parse_rate_dropped_total,
+            ),
+            counter(
+                "party_filtered_events_total",
+                "Decoded events not emitted because no party is in PARTY_ALLOWLIST.",
+                &self.party_filtered_events_total,
+            ),
+            counter(
+                "encode_failures_total",
+                "WebSocket messages that failed to serialize and were replaced by an Error frame.",
+                &self.encode_failures_total,
+            ),
+            counter(
+                "throttled_events_total",
+                "Live events not sent to a connection over WS_MAX_MSGS_PER_SEC with WS_THROTTLE_POLICY=drop.",
+                &self.throttled_events_total,
+            ),
+            counter(
+                "ingested_events_total",
+                "Events accepted from POST /ingest.",
+                &self.ingested_events_total,
+            ),
+            counter(
+                "ingest_rejected_lines_total",
+                "POST /ingest lines rejected as malformed or invalid.",
+                &self.ingest_rejected_lines_total,
+            ),
+            gauge(
+                "connected_clients",
+                "Currently connected WebSocket clients.",
+                load(&self.connected_clients),
+            ),
+            counter(
+                "reconnect_count",
+                "Logs subscriptions re-established after the first one.",
+                &self.reconnect_count,
+            ),
+            gauge(
+                "subscription_uptime_seconds",
+                "Seconds since the latest subscribe among live subscriptions; 0 while none is live.",
+                self.subscription_uptime_seconds() as f64,
+            ),
+            gauge(
+                "broadcast_oldest_lag_seconds",
+                "Age of the oldest event not yet received by every subscriber.",
+                broadcast_lag.as_secs_f64(),
+            ),
+        ]
+    }
+
+    pub fn render(&self, broadcast_lag: Duration) -> String {
+        let mut out = String::new();
+        for sample in self.samples(broadcast_lag) {
+            let _ = writeln!(out, "# HELP {} {}", sample.name, sample.help);
+            let _ = writeln!(out, "# TYPE {} {}", sample.name, sample.kind.name());
+            let _ = writeln!(out, "{} {}", sample.name, sample.value);
+        }
+        let _ = writeln!(out, "# HELP parse_seconds {}", PARSE_SECONDS_HELP);
+        let _ = writeln!(out, "# TYPE parse_seconds histogram");
+        for kind in EventKind::ALL {
+            let label = format!("type=\"{:?}\"", kind);
+            self.parse_seconds[kind.code() as usize].render(&mut out, "parse_seconds", &label);
+        }
+        if let Some(reason) = self.shutdown_reason.get() {
+            let _ = writeln!(out, "# HELP shutdown_reason {}", SHUTDOWN_REASON_HELP);
+            let _ = writeln!(out, "# TYPE shutdown_reason gauge");
+            let _ = writeln!(out, "shutdown_reason{{reason=\"{}\"}} 1", reason);
+        }
+        out
+    }
+}
+
+pub const PARSE_SECONDS_HELP: &str = "Time to decode and deserialize one event, by type.";
+pub const SHUTDOWN_REASON_HELP: &str = "Why the service is exiting; only present while it shuts down.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Only ever increases, apart from `POST /admin/stats/reset`.
+    Counter,
+    Gauge,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+        }
+    }
+}
+
+pub struct Sample {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: Kind,
+    pub value: f64,
+}
+
+/// Decode outcomes of one watched program, for `GET /stats/programs`.
+#[derive(Default)]
+pub struct ProgramCounters {
+    pub matched: AtomicU64,
+    pub unknown_discriminator: AtomicU64,
+    pub decode_failures: AtomicU64,
+    /// Decoded events by type, indexed by `EventKind::code`.
+    pub events: [AtomicU64; EventKind::ALL.len()],
+    /// Unix seconds of the latest decoded event; 0 if none yet.
+    pub last_seen_at: AtomicU64,
+    /// The program's subscriptions (or poller) that are up right now.
+    pub live_subscriptions: AtomicUsize,
+}
+
+impl ProgramCounters {
+    pub fn matched(&self, kind: EventKind) {
+        Metrics::inc(&self.matched);
+        Metrics::inc(&self.events[kind.code() as usize]);
+        self.last_seen_at.store(now_secs() as u64, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        for counter in [&self.matched, &self.unknown_discriminator, &self.decode_failures]
+            .into_iter()
+            .chain(&self.events)
+        {
+            counter.store(0, Ordering::Relaxed);
+        }
+        self.last_seen_at.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Upper bounds, in microseconds, of the `parse_seconds` buckets.
+pub const PARSE_BUCKETS_US: [u64; 8] = [1, 5, 10, 50, 100, 500, 1_000, 5_000];
+
+/// Fixed-bucket Prometheus histogram.
+#[derive(Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; PARSE_BUCKETS_US.len()],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, elapsed: Duration) {
+        let micros = elapsed.as_micros() as u64;
+        if let Some(i) = PARSE_BUCKETS_US.iter().position(|le| micros <= *le) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    fn reset(&self) {
+        for bucket in &self.buckets {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.count.store(0, Ordering::Relaxed);
+        self.sum_nanos.store(0, Ordering::Relaxed);
+    }
+
+    /// Per-bucket (not cumulative) counts with the overflow bucket last, total
+    /// count and sum in seconds.
+    pub fn totals(&self) -> (Vec<u64>, u64, f64) {
+        let mut buckets: Vec<u64> = self.buckets.iter().map(|b| b.load(Ordering::Relaxed)).collect();
+        let count = self.count.load(Ordering::Relaxed);
+        buckets.push(count.saturating_sub(buckets.iter().sum()));
+        let sum = self.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9;
+        (buckets, count, sum)
+    }
+
+    fn render(&self, out: &mut String, name: &str, label: &str) {
+        let mut cumulative = 0;
+        for (le, bucket) in PARSE_BUCKETS_US.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let le = *le as f64 / 1e6;
+            let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, label, le, cumulative);
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let sum = self.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9;
+        let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, label, count);
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, label, sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, label, count);
+    }
+}
+
+/// Holds one slot of the `connected_clients` gauge for the life of a connection.
+pub struct ClientGuard(Arc<Metrics>);
+
+impl ClientGuard {
+    /// Reserves a slot, or returns `None` when `max` clients are already connected.
+    pub fn acquire(metrics: &Arc<Metrics>, max: Option<usize>) -> Option<Self> {
+        metrics
+            .connected_clients
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| match max {
+                Some(max) if n >= max as u64 => None,
+                _ => Some(n + 1),
+            })
+            .ok()?;
+        Some(Self(metrics.clone()))
+    }
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.0.connected_clients.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{
+    channel::Tx,
+    config::Config,
+    encode,
+    log_style::icon,
+    shutdown::{self, ShutdownReason},
+    EventRecord,
+};
+use std::{fs, path::Path, sync::Arc};
+use tokio::{
+    io::AsyncWriteExt,
+    net::UnixListener,
+    sync::broadcast::error::RecvError,
+};
+use tracing::{info, warn};
+
+/// One event as a compact JSON line, newline included.
+fn line(record: &EventRecord, config: &Config) -> Option<String> {
+    let mut line = encode::to_json(record, config, &Default::default()).ok()?;
+    line.push('\n');
+    Some(line)
+}
+
+//
+// ---------------- NDJSON stdout sink
+//
+pub fn spawn_stdout_ndjson(tx: &Tx, config: Arc<Config>) {
+    let mut rx = tx.subscribe();
+
+    tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+
+        loop {
+            let record = match rx.recv().await {
+                Ok(record) => record,
+                Err(RecvError::Lagged(n)) => {
+                    warn!("stdout sink lagged, skipped {} events", n);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            let Some(line) = line(&record, &config) else {
+                continue;
+            };
+
+            // Flush per line so downstream tools see events promptly.
+            if stdout.write_all(line.as_bytes()).await.is_err() || stdout.flush().await.is_err() {
+                warn!("stdout closed, stopping NDJSON output");
+                break;
+            }
+        }
+    });
+}
+
+//
+// ---------------- NDJSON Unix socket sink (`UDS_PATH`)
+//
+// Every reader that connects gets its own subscription to the broadcast
+// channel and the same lines as stdout, from the moment it connects on.
+// Anything a reader writes is ignored. A reader that falls behind skips the
+// events it missed, like the stdout sink.
+pub fn spawn_uds_ndjson(tx: &Tx, config: Arc<Config>, path: &Path) {
+    // `Config` refused anything at the path but a stale socket.
+    let _ = fs::remove_file(path);
+    let listener = match UnixListener::bind(path) {
+        Ok(listener) => listener,
+        Err(e) => shutdown::exit(
+            ShutdownReason::FatalError,
+            &format!("cannot listen on UDS_PATH {}: {}", path.display(), e),
+        ),
+    };
+    info!("{} NDJSON on unix socket {}", icon("🚀"), path.display());
+
+    let tx = tx.clone();
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                continue;
+            };
+            let mut rx = tx.subscribe();
+            let config = config.clone();
+            tokio::spawn(async move {
+                loop {
+                    let record = match rx.recv().await {
+                        Ok(record) => record,
+                        Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => break,
+                    };
+                    let Some(line) = line(&record, &config) else {
+                        continue;
+                    };
+                    if stream.write_all(line.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+}
@@ -0,0 +1,227 @@
+use crate::{EventRecord, WebEvent};
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Default for `ORDER_STATE_CAPACITY`.
+pub const DEFAULT_CAPACITY: usize = 100_000;
+
+//
+// ---------------- Order-state projection
+//
+// Current state of every open order, folded from the event stream. Orders are
+// dropped once completed so memory stays proportional to open orders. Orders
+// that never complete (abandoned, or their completion was missed) would stay
+// forever, so the map is an LRU of at most `ORDER_STATE_CAPACITY` orders: the
+// one least recently created or accepted is evicted first.
+pub struct OrderStates {
+    orders: Mutex<LruCache<String, OrderState>>,
+}
+
+impl Default for OrderStates {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct OrderState {
+    order_id: Option<u64>,
+    customer: Option<String>,
+    courier: Option<String>,
+    amount: Option<u64>,
+    /// Unix seconds; `Some` once the order is accepted.
+    accepted_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InFlightOrder {
+    pub order: String,
+    pub order_id: Option<u64>,
+    pub courier: String,
+    pub amount: Option<u64>,
+    pub accepted_at: i64,
+}
+
+impl OrderStates {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            orders: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Folds `record` in; true when that evicted another open order.
+    pub fn apply(&self, record: &EventRecord) -> bool {
+        let mut orders = self.orders.lock().unwrap();
+        let evicts = |orders: &LruCache<String, OrderState>, order: &str| {
+            orders.len() == orders.cap().get() && !orders.contains(order)
+        };
+
+        match &record.event {
+            WebEvent::OrderCreated {
+                order,
+                order_id,
+                customer,
+                amount,
+            } => {
+                let evicted = evicts(&orders, order);
+                let state = orders.get_or_insert_mut(order.clone(), OrderState::default);
+                state.order_id = Some(*order_id);
+                state.customer = Some(customer.clone());
+                state.amount = Some(*amount);
+                evicted
+            }
+            WebEvent::OrderAccepted { order, courier } => {
+                let evicted = evicts(&orders, order);
+                let state = orders.get_or_insert_mut(order.clone(), OrderState::default);
+                state.courier = Some(courier.clone());
+                state.accepted_at = Some(record.block_time.unwrap_or_else(now_secs));
+                evicted
+            }
+            WebEvent::OrderCompleted { order, .. } => {
+                orders.pop(order);
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Fills in creation details read from the order account, for an order
+    /// created before startup; what the events already told is kept. True
+    /// when that evicted another open order.
+    pub fn backfill(&self, order: &str, order_id: u64, customer: String, amount: u64) -> bool {
+        let mut orders = self.orders.lock().unwrap();
+        let evicted = orders.len() == orders.cap().get() && !orders.contains(order);
+        let state = orders.get_or_insert_mut(order.to_string(), OrderState::default);
+        state.order_id.get_or_insert(order_id);
+        state.customer.get_or_insert(customer);
+        state.amount.get_or_insert(amount);
+        evicted
+    }
+
+    /// Amount from the order's `OrderCreated`, while the order is open.
+    pub fn amount(&self, order: &str) -> Option<u64> {
+        self.orders.lock().unwrap().peek(order)?.amount
+    }
+
+    /// Customer from the order's `OrderCreated`, while the order is open.
+    pub fn customer(&self, order: &str) -> Option<String> {
+        self.orders.lock().unwrap().peek(order)?.customer.clone()
+    }
+
+    pub(crate) fn snapshot(&self) -> HashMap<String, OrderState> {
+        let orders = self.orders.lock().unwrap();
+        orders.iter().map(|(order, state)| (order.clone(), state.clone())).collect()
+    }
+
+    /// Replaces the open orders; beyond the capacity, the earliest accepted
+    /// (then the never accepted) are left out. Orders are put back in that
+    /// order, so the least recent one is also the next to be evicted.
+    pub(crate) fn restore(&self, orders: HashMap<String, OrderState>) {
+        let mut restored: Vec<_> = orders.into_iter().collect();
+        // `None` sorts first, but not-yet-accepted orders are the newest.
+        restored.sort_by_key(|(_, state)| (state.accepted_at.is_none(), state.accepted_at));
+        let mut orders = self.orders.lock().unwrap();
+        orders.clear();
+        for (order, state) in restored {
+            orders.put(order, state);
+        }
+    }
+
+    /// Accepted-but-not-completed orders, oldest acceptance first.
+    pub fn in_flight(&self) -> Vec<InFlightOrder> {
+        let orders = self.orders.lock().unwrap();
+        let mut in_flight: Vec<InFlightOrder> = orders
+            .iter()
+            .filter_map(|(order, state)| {
+                Some(InFlightOrder {
+                    order: order.clone(),
+                    order_id: state.order_id,
+                    courier: state.courier.clone()?,
+                    amount: state.amount,
+                    accepted_at: state.accepted_at?,
+                })
+            })
+            .collect();
+        in_flight.sort_by_key(|o| o.accepted_at);
+        in_flight
+    }
+}
+
+pub fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or_default()
+}
+
+pub fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accepted(order: &str) -> EventRecord {
+        EventRecord {
+            block_time: Some(1),
+            ..EventRecord::test(WebEvent::OrderAccepted {
+                order: order.to_string(),
+                courier: "k1".to_string(),
+            })
+        }
+    }
+
+    #[test]
+    fn evicts_the_least_recent_order_beyond_capacity() {
+        let orders = OrderStates::new(2);
+        assert!(!orders.apply(&accepted("o1")));
+        assert!(!orders.apply(&accepted("o2")));
+        // Touching o1 again makes o2 the one to go.
+        assert!(!orders.apply(&accepted("o1")));
+        assert!(orders.apply(&accepted("o3")));
+        let mut open: Vec<_> = orders.in_flight().into_iter().map(|o| o.order).collect();
+        open.sort();
+        assert_eq!(open, ["o1", "o3"]);
+    }
+
+    #[test]
+    fn backfill_fills_in_only_what_events_left_out() {
+        let orders = OrderStates::default();
+        orders.apply(&accepted("o1"));
+        assert!(!orders.backfill("o1", 7, "c1".to_string(), 100));
+        assert_eq!((orders.customer("o1"), orders.amount("o1")), (Some("c1".to_string()), Some(100)));
+        assert_eq!(orders.in_flight()[0].order_id, Some(7));
+
+        orders.backfill("o1", 7, "c2".to_string(), 200);
+        assert_eq!((orders.customer("o1"), orders.amount("o1")), (Some("c1".to_string()), Some(100)));
+    }
+
+    #[test]
+    fn restore_beyond_capacity_keeps_the_latest_orders() {
+        let state = |accepted_at: Option<i64>| OrderState {
+            courier: accepted_at.map(|_| "k1".to_string()),
+            accepted_at,
+            ..OrderState::default()
+        };
+        let orders = OrderStates::new(2);
+        orders.restore(HashMap::from([
+            ("early".to_string(), state(Some(1))),
+            ("late".to_string(), state(Some(5))),
+            ("created".to_string(), state(None)),
+        ]));
+        let mut open: Vec<_> = orders.snapshot().into_keys().collect();
+        open.sort();
+        assert_eq!(open, ["created", "late"]);
+    }
+}
@@ -0,0 +1,150 @@
+use crate::{
+    log_style::icon,
+    metrics::{Kind, PARSE_BUCKETS_US, PARSE_SECONDS_HELP, SHUTDOWN_REASON_HELP},
+    state::AppState,
+    EventKind,
+};
+use serde_json::{json, Value};
+use std::{
+    sync::{atomic::Ordering, OnceLock},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::warn;
+
+const SERVICE_NAME: &str = "block_delivery_service";
+const FINAL_PUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// When the exporter started, the start of every cumulative series.
+static STARTED_AT: OnceLock<u64> = OnceLock::new();
+
+//
+// ---------------- OTLP metrics push (`OTEL_EXPORTER_OTLP_ENDPOINT`)
+//
+// Every interval, POSTs the same counters and gauges `/metrics` serves to
+// `<endpoint>/v1/metrics` as OTLP/HTTP JSON, cumulative since start (or the
+// latest `/admin/stats/reset`). A failed push is logged and retried with the
+// next interval's values.
+pub fn spawn(state: AppState, endpoint: &str, interval: Duration) {
+    let _ = STARTED_AT.set(unix_nanos(SystemTime::now()));
+    let url = format!("{}/v1/metrics", endpoint);
+    thread::spawn(move || {
+        let client = reqwest::blocking::Client::new();
+        loop {
+            thread::sleep(interval);
+            push(&client, &url, &state);
+        }
+    });
+}
+
+/// One last push on shutdown, so the collector sees `shutdown_reason`. Runs
+/// on its own thread, as the blocking client must not run on a Tokio worker,
+/// and gives up after `FINAL_PUSH_TIMEOUT`.
+pub fn push_final(state: &AppState) {
+    let Some(endpoint) = &state.config.otlp_endpoint else {
+        return;
+    };
+    let url = format!("{}/v1/metrics", endpoint);
+    let state = state.clone();
+    let _ = thread::spawn(move || {
+        if let Ok(client) = reqwest::blocking::Client::builder()
+            .timeout(FINAL_PUSH_TIMEOUT)
+            .build()
+        {
+            push(&client, &url, &state);
+        }
+    })
+    .join();
+}
+
+fn push(client: &reqwest::blocking::Client, url: &str, state: &AppState) {
+    let started_at = STARTED_AT.get().copied().unwrap_or_default();
+    let reset_at = state.metrics.reset_at.load(Ordering::Relaxed);
+    let start = started_at.max(reset_at * 1_000_000_000);
+    let body = export(state, start, unix_nanos(SystemTime::now()));
+    let result = client
+        .post(url)
+        .header("content-type", "application/json")
+        .body(body.to_string())
+        .send()
+        .and_then(|response| response.error_for_status());
+    if let Err(e) = result {
+        warn!("{} OTLP metrics push to {} failed: {}", icon("⚠️"), url, e);
+    }
+}
+
+fn unix_nanos(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64
+}
+
+/// An `ExportMetricsServiceRequest` in the OTLP JSON encoding, where 64-bit
+/// integers are strings.
+fn export(state: &AppState, start: u64, now: u64) -> Value {
+    let (start, now) = (start.to_string(), now.to_string());
+    let mut metrics: Vec<Value> = state
+        .metrics
+        .samples(state.broadcast_lag())
+        .into_iter()
+        .map(|sample| {
+            let point = json!({
+                "startTimeUnixNano": start,
+                "timeUnixNano": now,
+                "asDouble": sample.value,
+            });
+            let data = match sample.kind {
+                Kind::Counter => ("sum", json!({
+                    "dataPoints": [point],
+                    "aggregationTemporality": 2,
+                    "isMonotonic": true,
+                })),
+                Kind::Gauge => ("gauge", json!({ "dataPoints": [point] })),
+            };
+            json!({ "name": sample.name, "description": sample.help, data.0: data.1 })
+        })
+        .collect();
+
+    let points: Vec<Value> = EventKind::ALL
+        .iter()
+        .map(|kind| {
+            let (buckets, count, sum) = state.metrics.parse_seconds[kind.code() as usize].totals();
+            json!({
+                "attributes": [{ "key": "type", "value": { "stringValue": format!("{:?}", kind) } }],
+                "startTimeUnixNano": start,
+                "timeUnixNano": now,
+                "count": count.to_string(),
+                "sum": sum,
+                "bucketCounts": buckets.iter().map(u64::to_string).collect::<Vec<_>>(),
+                "explicitBounds": PARSE_BUCKETS_US.iter().map(|us| *us as f64 / 1e6).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    metrics.push(json!({
+        "name": "parse_seconds",
+        "description": PARSE_SECONDS_HELP,
+        "unit": "s",
+        "histogram": { "dataPoints": points, "aggregationTemporality": 2 },
+    }));
+    if let Some(reason) = state.metrics.shutdown_reason.get() {
+        metrics.push(json!({
+            "name": "shutdown_reason",
+            "description": SHUTDOWN_REASON_HELP,
+            "gauge": { "dataPoints": [{
+                "attributes": [{ "key": "reason", "value": { "stringValue": reason } }],
+                "timeUnixNano": now,
+                "asDouble": 1.0,
+            }] },
+        }));
+    }
+
+    json!({
+        "resourceMetrics": [{
+            "resource": {
+                "attributes": [{ "key": "service.name", "value": { "stringValue": SERVICE_NAME } }],
+            },
+            "scopeMetrics": [{
+                "scope": { "name": SERVICE_NAME },
+                "metrics": metrics,
+            }],
+        }],
+    })
+}
@@ -0,0 +1,73 @@
+use crate::EventRecord;
+use solana_sdk::pubkey::Pubkey;
+use std::{collections::VecDeque, sync::Mutex};
+
+/// A broadcast withheld while paused, with the program it came from.
+pub type Held = (Option<Pubkey>, EventRecord);
+
+//
+// ---------------- Broadcast pause (`/admin/pause`)
+//
+// While paused, broadcasts are queued instead of sent, up to `capacity`; past
+// that the oldest held event is discarded. Sending happens under the lock so
+// a resume flush can't interleave with newer events.
+pub struct Pause {
+    capacity: usize,
+    held: Mutex<Option<VecDeque<Held>>>,
+}
+
+impl Pause {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            held: Mutex::new(None),
+        }
+    }
+
+    /// Returns false if already paused.
+    pub fn pause(&self) -> bool {
+        let mut held = self.held.lock().unwrap();
+        if held.is_some() {
+            return false;
+        }
+        *held = Some(VecDeque::new());
+        true
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.held.lock().unwrap().is_some()
+    }
+
+    /// `seq` of the oldest event held back, if any.
+    pub fn first_held_seq(&self) -> Option<u64> {
+        let held = self.held.lock().unwrap();
+        held.as_ref()?.front().map(|(_, record)| record.seq)
+    }
+
+    /// Sends `item` now, or holds it while paused. Returns true when holding
+    /// it evicted an older event.
+    pub fn send_or_hold(&self, item: Held, send: impl Fn(Held)) -> bool {
+        let mut held = self.held.lock().unwrap();
+        let Some(queue) = held.as_mut() else {
+            send(item);
+            return false;
+        };
+
+        queue.push_back(item);
+        if queue.len() > self.capacity {
+            queue.pop_front();
+            return true;
+        }
+        false
+    }
+
+    /// Sends everything held, oldest first, and unpauses. Returns how many
+    /// events were flushed, or `None` if not paused.
+    pub fn resume(&self, send: impl Fn(Held)) -> Option<usize> {
+        let mut held = self.held.lock().unwrap();
+        let queue = held.take()?;
+        let flushed = queue.len();
+        queue.into_iter().for_each(send);
+        Some(flushed)
+    }
+}
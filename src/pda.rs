@@ -0,0 +1,80 @@
+use crate::WebEvent;
+use solana_sdk::pubkey::Pubkey;
+use serde::{Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+//
+// ---------------- Order PDA seed template
+//
+// `ORDER_PDA_SEEDS` is a comma-separated seed list, e.g. `order,{order_id}`.
+// Plain parts are UTF-8 literals; `{order_id}` is the u64 little-endian and
+// `{customer}` the customer pubkey bytes.
+#[derive(Debug, Clone)]
+pub struct SeedTemplate(Vec<SeedPart>);
+
+#[derive(Debug, Clone)]
+enum SeedPart {
+    Literal(Vec<u8>),
+    OrderId,
+    Customer,
+}
+
+impl FromStr for SeedTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split(',')
+            .map(|part| match part.trim() {
+                "{order_id}" => Ok(SeedPart::OrderId),
+                "{customer}" => Ok(SeedPart::Customer),
+                p if p.starts_with('{') => Err(format!("unknown seed {}", p)),
+                p => Ok(SeedPart::Literal(p.as_bytes().to_vec())),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self(parts))
+    }
+}
+
+impl fmt::Display for SeedTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, part) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            match part {
+                SeedPart::Literal(bytes) => f.write_str(&String::from_utf8_lossy(bytes))?,
+                SeedPart::OrderId => f.write_str("{order_id}")?,
+                SeedPart::Customer => f.write_str("{customer}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for SeedTemplate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl SeedTemplate {
+    /// Whether `order` is the PDA derived for this event; `None` when the
+    /// event lacks a field the template needs.
+    pub fn verify(&self, event: &WebEvent, program_id: &Pubkey) -> Option<bool> {
+        let mut seeds: Vec<Vec<u8>> = Vec::with_capacity(self.0.len());
+        for part in &self.0 {
+            seeds.push(match part {
+                SeedPart::Literal(bytes) => bytes.clone(),
+                SeedPart::OrderId => event.order_id()?.to_le_bytes().to_vec(),
+                SeedPart::Customer => {
+                    Pubkey::from_str(event.customer()?).ok()?.to_bytes().to_vec()
+                }
+            });
+        }
+
+        let seeds: Vec<&[u8]> = seeds.iter().map(Vec::as_slice).collect();
+        let (pda, _bump) = Pubkey::find_program_address(&seeds, program_id);
+        Some(pda.to_string() == event.order())
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{
+    events::EventSource,
+    finality::Finality as Commitment,
+    EventRecord, WebEvent,
+};
+use prost::Message;
+
+//
+// ---------------- Protobuf encoding (`?encoding=protobuf`)
+//
+// The messages of `proto/events.proto`, written out with prost's derives
+// rather than generated, so the build needs no `protoc`. Keep the two in
+// step: same names, same tags.
+#[derive(Clone, PartialEq, Message)]
+pub struct OrderCreated {
+    #[prost(string, tag = "1")]
+    pub order: String,
+    #[prost(uint64, tag = "2")]
+    pub order_id: u64,
+    #[prost(string, tag = "3")]
+    pub customer: String,
+    #[prost(uint64, tag = "4")]
+    pub amount: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct OrderAccepted {
+    #[prost(string, tag = "1")]
+    pub order: String,
+    #[prost(string, tag = "2")]
+    pub courier: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct OrderCompleted {
+    #[prost(string, tag = "1")]
+    pub order: String,
+    #[prost(uint64, tag = "2")]
+    pub order_id: u64,
+    #[prost(string, tag = "3")]
+    pub courier: String,
+    #[prost(uint64, tag = "4")]
+    pub amount: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum Finality {
+    Unspecified = 0,
+    Processed = 1,
+    Confirmed = 2,
+    Finalized = 3,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Event {
+    #[prost(oneof = "event::Event", tags = "1, 2, 3")]
+    pub event: Option<event::Event>,
+    #[prost(uint64, tag = "10")]
+    pub seq: u64,
+    #[prost(uint64, optional, tag = "11")]
+    pub slot: Option<u64>,
+    #[prost(string, optional, tag = "12")]
+    pub signature: Option<String>,
+    #[prost(int64, optional, tag = "13")]
+    pub block_time: Option<i64>,
+    #[prost(uint32, optional, tag = "14")]
+    pub tx_index: Option<u32>,
+    #[prost(uint32, optional, tag = "15")]
+    pub log_index: Option<u32>,
+    #[prost(bool, optional, tag = "16")]
+    pub pda_valid: Option<bool>,
+    #[prost(enumeration = "Finality", tag = "17")]
+    pub finality: i32,
+    #[prost(uint64, optional, tag = "18")]
+    pub received_at: Option<u64>,
+    #[prost(string, optional, tag = "19")]
+    pub source: Option<String>,
+    #[prost(string, optional, tag = "20")]
+    pub raw_base64: Option<String>,
+    #[prost(uint32, optional, tag = "21")]
+    pub type_code: Option<u32>,
+}
+
+pub mod event {
+    // The names protoc would give the oneof's cases.
+    #[allow(clippy::enum_variant_names)]
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Event {
+        #[prost(message, tag = "1")]
+        OrderCreated(super::OrderCreated),
+        #[prost(message, tag = "2")]
+        OrderAccepted(super::OrderAccepted),
+        #[prost(message, tag = "3")]
+        OrderCompleted(super::OrderCompleted),
+    }
+}
+
+/// The encoded `Event`; `None` for the service's own events, which the
+/// schema doesn't cover.
+pub fn encode(record: &EventRecord, include_raw: bool) -> Option<Vec<u8>> {
+    Some(message(record, include_raw)?.encode_to_vec())
+}
+
+fn message(record: &EventRecord, include_raw: bool) -> Option<Event> {
+    let event = match record.event.clone() {
+        WebEvent::OrderCreated {
+            order,
+            order_id,
+            customer,
+            amount,
+        } => event::Event::OrderCreated(OrderCreated {
+            order,
+            order_id,
+            customer,
+            amount,
+        }),
+        WebEvent::OrderAccepted { order, courier } => {
+            event::Event::OrderAccepted(OrderAccepted { order, courier })
+        }
+        WebEvent::OrderCompleted {
+            order,
+            order_id,
+            courier,
+            amount,
+        } => event::Event::OrderCompleted(OrderCompleted {
+            order,
+            order_id,
+            courier,
+            amount,
+        }),
+        _ => return None,
+    };
+    let finality = match record.finality {
+        None => Finality::Unspecified,
+        Some(Commitment::Processed) => Finality::Processed,
+        Some(Commitment::Confirmed) => Finality::Confirmed,
+        Some(Commitment::Finalized) => Finality::Finalized,
+    };
+    Some(Event {
+        event: Some(event),
+        seq: record.seq,
+        slot: record.slot,
+        signature: record.signature.clone(),
+        block_time: record.block_time,
+        tx_index: record.tx_index,
+        log_index: record.log_index,
+        pda_valid: record.pda_valid,
+        finality: finality as i32,
+        received_at: record.received_at,
+        source: record.source.map(|source| match source {
+            EventSource::External => "external".to_string(),
+        }),
+        raw_base64: record.raw_base64.clone().filter(|_| include_raw),
+        type_code: Some(record.event.type_code().into()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn web_event(event: event::Event) -> WebEvent {
+        match event {
+            event::Event::OrderCreated(e) => WebEvent::OrderCreated {
+                order: e.order,
+                order_id: e.order_id,
+                customer: e.customer,
+                amount: e.amount,
+            },
+            event::Event::OrderAccepted(e) => WebEvent::OrderAccepted {
+                order: e.order,
+                courier: e.courier,
+            },
+            event::Event::OrderCompleted(e) => WebEvent::OrderCompleted {
+                order: e.order,
+                order_id: e.order_id,
+                courier: e.courier,
+                amount: e.amount,
+            },
+        }
+    }
+
+    #[test]
+    fn order_events_round_trip() {
+        let events = [
+            WebEvent::OrderCreated {
+                order: "o1".to_string(),
+                order_id: 7,
+                customer: "c1".to_string(),
+                amount: u64::MAX,
+            },
+            WebEvent::OrderAccepted {
+                order: "o1".to_string(),
+                courier: "k1".to_string(),
+            },
+            WebEvent::OrderCompleted {
+                order: "o1".to_string(),
+                order_id: 7,
+                courier: "k1".to_string(),
+                amount: 100,
+            },
+        ];
+        for (seq, event) in events.into_iter().enumerate() {
+            let record = EventRecord {
+                slot: Some(1200),
+                signature: Some("sig".to_string()),
+                tx_index: Some(3),
+                log_index: Some(0),
+                finality: Some(Commitment::Finalized),
+                raw_base64: Some("AAAA".to_string()),
+                seq: seq as u64 + 1,
+                ..EventRecord::test(event)
+            };
+            let bytes = encode(&record, false).unwrap();
+            let decoded = Event::decode(bytes.as_slice()).unwrap();
+            assert_eq!(web_event(decoded.event.clone().unwrap()), record.event);
+            assert_eq!(decoded.type_code, Some(seq as u32));
+            assert_eq!(decoded.seq, record.seq);
+            assert_eq!((decoded.slot, decoded.tx_index, decoded.log_index), (Some(1200), Some(3), Some(0)));
+            assert_eq!(decoded.finality(), Finality::Finalized);
+            assert_eq!((decoded.block_time, decoded.raw_base64), (None, None));
+        }
+
+        let tick = EventRecord {
+            seq: 4,
+            ..EventRecord::test(WebEvent::Tick {
+                window_secs: 60,
+                created: 0,
+                accepted: 0,
+                completed: 0,
+                total_volume: 0,
+            })
+        };
+        assert!(encode(&tick, false).is_none());
+    }
+}
@@ -0,0 +1,62 @@
+use crate::{state::AppState, EventRecord};
+use solana_sdk::pubkey::Pubkey;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+pub type ReorderTx = mpsc::Sender<(Pubkey, EventRecord)>;
+
+//
+// ---------------- Slot reordering buffer
+//
+// Holds each event for up to `hold` and releases events in slot order.
+// When an event's hold expires, it is released together with every buffered
+// event of a lower or equal slot, so latency is bounded by `hold` and output
+// stays monotonic as long as events arrive less than `hold` out of order.
+pub fn spawn(state: AppState, rx: mpsc::Receiver<(Pubkey, EventRecord)>, hold: Duration) {
+    thread::spawn(move || {
+        let mut buffer: BTreeMap<(u64, u64), (Pubkey, EventRecord)> = BTreeMap::new();
+        // Arrival order: (release deadline, slot).
+        let mut deadlines: VecDeque<(Instant, u64)> = VecDeque::new();
+        let mut arrival = 0u64;
+
+        loop {
+            let timeout = deadlines
+                .front()
+                .map(|(deadline, _)| deadline.saturating_duration_since(Instant::now()))
+                .unwrap_or(hold);
+
+            match rx.recv_timeout(timeout) {
+                Ok((program_id, record)) => {
+                    let slot = record.slot.unwrap_or(0);
+                    arrival += 1;
+                    buffer.insert((slot, arrival), (program_id, record));
+                    deadlines.push_back((Instant::now() + hold, slot));
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            let now = Instant::now();
+            let mut release_up_to = None;
+            while let Some(&(deadline, slot)) = deadlines.front() {
+                if deadline > now {
+                    break;
+                }
+                deadlines.pop_front();
+                release_up_to = release_up_to.max(Some(slot));
+            }
+
+            let Some(max_slot) = release_up_to else {
+                continue;
+            };
+            let rest = buffer.split_off(&(max_slot + 1, 0));
+            for (_, (program_id, record)) in std::mem::replace(&mut buffer, rest) {
+                state.publish(Some(&program_id), record);
+            }
+        }
+    });
+}
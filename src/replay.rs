@@ -0,0 +1,153 @@
+use crate::EventRecord;
+use solana_sdk::pubkey::Pubkey;
+use std::{collections::VecDeque, sync::Mutex};
+
+//
+// ---------------- Bounded in-memory event buffer
+//
+// Keeps the most recent `capacity` events; the oldest is evicted first. Every
+// pushed event gets the next `seq`, so consumers can tell buffered events
+// from ones they already received live. Events are kept with the program
+// they were published for, so `/ws/:program_id` replays only its own.
+pub struct ReplayBuffer {
+    capacity: usize,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    last_seq: u64,
+    events: VecDeque<(Option<Pubkey>, EventRecord)>,
+}
+
+impl Inner {
+    /// Buffered events of `program_id` (all of them for `None`), oldest first.
+    fn of<'a>(&'a self, program_id: Option<&'a Pubkey>) -> impl Iterator<Item = &'a EventRecord> + 'a {
+        self.events
+            .iter()
+            .filter(move |(program, _)| program_id.is_none_or(|id| program.as_ref() == Some(id)))
+            .map(|(_, event)| event)
+    }
+}
+
+impl ReplayBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::new(Inner {
+                last_seq: 0,
+                events: VecDeque::with_capacity(capacity),
+            }),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// `seq` of the latest published event, 0 before the first.
+    pub fn last_seq(&self) -> u64 {
+        self.inner.lock().unwrap().last_seq
+    }
+
+    /// Stores `event`, published for `program_id`, and returns it with its
+    /// assigned `seq`.
+    pub fn push(&self, program_id: Option<&Pubkey>, mut event: EventRecord) -> EventRecord {
+        let mut inner = self.inner.lock().unwrap();
+        inner.last_seq += 1;
+        event.seq = inner.last_seq;
+        if self.capacity == 0 {
+            return event;
+        }
+
+        if inner.events.len() == self.capacity {
+            inner.events.pop_front();
+        }
+        inner.events.push_back((program_id.copied(), event.clone()));
+        event
+    }
+
+    /// Oldest-first copy of the buffered events matching `pred`.
+    pub fn filter(&self, pred: impl Fn(&EventRecord) -> bool) -> Vec<EventRecord> {
+        let inner = self.inner.lock().unwrap();
+        inner.of(None).filter(|e| pred(e)).cloned().collect()
+    }
+
+    /// Every buffered event of `program_id` (of all programs for `None`),
+    /// oldest first.
+    pub fn all(&self, program_id: Option<&Pubkey>) -> Backlog {
+        let inner = self.inner.lock().unwrap();
+        Backlog {
+            events: inner.of(program_id).cloned().collect(),
+            last_seq: inner.last_seq,
+        }
+    }
+
+    /// Buffered events of `program_id` (of all programs for `None`) after
+    /// `cursor`, or `None` when events after it were already evicted (or the
+    /// cursor is unknown). `seq` and signature cursors are placed in the
+    /// whole buffer, so they work whichever route the client came from.
+    pub fn after(&self, cursor: &Cursor, program_id: Option<&Pubkey>) -> Option<Backlog> {
+        let inner = self.inner.lock().unwrap();
+        let events: Vec<EventRecord> = match cursor {
+            Cursor::Slot(since) => {
+                let mut events: Vec<EventRecord> = inner
+                    .of(program_id)
+                    .filter(|e| e.slot.is_some_and(|slot| slot > *since))
+                    .cloned()
+                    .collect();
+                events.sort_by_key(|e| e.slot);
+                events
+            }
+            Cursor::Seq(seq) => {
+                let oldest = inner.events.front().map_or(inner.last_seq + 1, |(_, e)| e.seq);
+                if *seq + 1 < oldest || *seq > inner.last_seq {
+                    return None;
+                }
+                inner.of(program_id).filter(|e| e.seq > *seq).cloned().collect()
+            }
+            Cursor::Signature(signature) => {
+                let at = inner
+                    .events
+                    .iter()
+                    .rposition(|(_, e)| e.signature.as_ref() == Some(signature))?;
+                let after = inner.events[at].1.seq;
+                inner.of(program_id).filter(|e| e.seq > after).cloned().collect()
+            }
+        };
+        Some(Backlog {
+            events,
+            last_seq: inner.last_seq,
+        })
+    }
+}
+
+/// Where a reconnecting client wants replay to start from (exclusive).
+#[derive(Debug, Clone)]
+pub enum Cursor {
+    Slot(u64),
+    Seq(u64),
+    Signature(String),
+}
+
+/// Replayed events, plus the latest `seq` assigned when they were taken;
+/// live events up to it are covered by the backlog.
+pub struct Backlog {
+    pub events: Vec<EventRecord>,
+    pub last_seq: u64,
+}
+
+impl Backlog {
+    /// Keeps only the newest `max` events; returns how many were dropped.
+    pub fn cap(&mut self, max: usize) -> usize {
+        let dropped = self.events.len().saturating_sub(max);
+        self.events.drain(..dropped);
+        dropped
+    }
+
+    /// Leaves out the events from `seq` on, which haven't been broadcast
+    /// yet; clients get them live once they are.
+    pub fn withhold_from(&mut self, seq: u64) {
+        self.events.retain(|record| record.seq < seq);
+        self.last_seq = self.last_seq.min(seq - 1);
+    }
+}
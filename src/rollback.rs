@@ -0,0 +1,216 @@
+use crate::{finality::Finality, log_style::icon, metrics::Metrics, state::AppState, EventRecord, WebEvent};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    str::FromStr,
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+use solana_client::rpc_client::RpcClient;
+use tracing::warn;
+
+/// Slots with events awaiting finalization at most; the oldest are given up
+/// on beyond this, e.g. while RPC is down.
+const MAX_PENDING_SLOTS: usize = 4096;
+
+/// Most signatures `getSignatureStatuses` takes per call.
+const STATUS_BATCH: usize = 256;
+
+/// The transactions broadcast from one slot: `(program, signature)`.
+type Pending = HashSet<(Option<Pubkey>, String)>;
+
+//
+// ---------------- Rollback detection (`ROLLBACK_DETECTION`)
+//
+// Remembers the slot and signature of every chain event published before
+// it was finalized. Every `ROLLBACK_POLL_MS` the finalized root is read
+// over RPC, and the remembered slots at or below it are compared with the
+// blocks that were actually finalized in that range: a slot that isn't
+// among them was abandoned. Its transactions are looked up with
+// `getSignatureStatuses`, and each one that didn't land in another slot
+// instead gets a `WebEvent::Reverted`. Failed lookups are retried on the next
+// round.
+#[derive(Default)]
+pub struct RollbackWatch {
+    pending: Mutex<BTreeMap<u64, Pending>>,
+}
+
+impl RollbackWatch {
+    pub fn apply(&self, program_id: Option<&Pubkey>, record: &EventRecord) {
+        if record.event.is_own() || record.source.is_some() || record.finality == Some(Finality::Finalized) {
+            return;
+        }
+        let (Some(slot), Some(signature)) = (record.slot, &record.signature) else {
+            return;
+        };
+        let mut pending = self.pending.lock().unwrap();
+        pending
+            .entry(slot)
+            .or_default()
+            .insert((program_id.copied(), signature.clone()));
+        while pending.len() > MAX_PENDING_SLOTS {
+            pending.pop_first();
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pending.lock().unwrap().is_empty()
+    }
+
+    /// Takes the slots at or below `root`.
+    fn due(&self, root: u64) -> BTreeMap<u64, Pending> {
+        let mut pending = self.pending.lock().unwrap();
+        let later = pending.split_off(&(root + 1));
+        std::mem::replace(&mut *pending, later)
+    }
+
+    /// Puts back slots whose check failed.
+    fn restore(&self, due: BTreeMap<u64, Pending>) {
+        self.pending.lock().unwrap().extend(due);
+    }
+}
+
+/// The transactions of `due` slots missing from `finalized`, in slot order.
+fn abandoned(due: BTreeMap<u64, Pending>, finalized: &HashSet<u64>) -> Vec<(u64, Option<Pubkey>, String)> {
+    due.into_iter()
+        .filter(|(slot, _)| !finalized.contains(slot))
+        .flat_map(|(slot, txs)| txs.into_iter().map(move |(program_id, signature)| (slot, program_id, signature)))
+        .collect()
+}
+
+/// Of the `abandoned` transactions, those that didn't land elsewhere;
+/// `landed` maps signatures to the slot they are in now.
+fn reverted(
+    abandoned: Vec<(u64, Option<Pubkey>, String)>,
+    landed: &HashMap<String, u64>,
+) -> Vec<(u64, Option<Pubkey>, String)> {
+    abandoned
+        .into_iter()
+        .filter(|(slot, _, signature)| landed.get(signature).is_none_or(|landed| landed == slot))
+        .collect()
+}
+
+/// The current slot of each of `signatures` that RPC knows about; `None` if
+/// a lookup failed.
+fn landed_slots<'a>(rpc: &RpcClient, signatures: impl Iterator<Item = &'a String>) -> Option<HashMap<String, u64>> {
+    let signatures: Vec<_> = signatures.filter_map(|s| Signature::from_str(s).ok()).collect();
+    let mut landed = HashMap::new();
+    for batch in signatures.chunks(STATUS_BATCH) {
+        let statuses = rpc.get_signature_statuses(batch).ok()?.value;
+        for (signature, status) in batch.iter().zip(statuses) {
+            if let Some(status) = status {
+                landed.insert(signature.to_string(), status.slot);
+            }
+        }
+    }
+    Some(landed)
+}
+
+pub fn spawn(state: AppState, interval: Duration) {
+    let Some(watch) = state.rollback.clone() else {
+        return;
+    };
+    thread::spawn(move || {
+        loop {
+            thread::sleep(interval);
+            if watch.is_empty() {
+                continue;
+            }
+
+            let finalized = CommitmentConfig::finalized();
+            let Ok(root) = state.rpc.get_slot_with_commitment(finalized) else {
+                Metrics::inc(&state.metrics.rollback_check_failures_total);
+                continue;
+            };
+            let due = watch.due(root);
+            let Some(&first) = due.keys().next() else {
+                continue;
+            };
+            let blocks = match state.rpc.get_blocks_with_commitment(first, Some(root), finalized) {
+                Ok(blocks) => blocks.into_iter().collect::<HashSet<_>>(),
+                Err(_) => {
+                    Metrics::inc(&state.metrics.rollback_check_failures_total);
+                    watch.restore(due);
+                    continue;
+                }
+            };
+
+            let candidates = abandoned(due.clone(), &blocks);
+            let landed = match landed_slots(&state.rpc, candidates.iter().map(|(_, _, s)| s)) {
+                Some(landed) => landed,
+                None => {
+                    Metrics::inc(&state.metrics.rollback_check_failures_total);
+                    watch.restore(due);
+                    continue;
+                }
+            };
+
+            for (slot, program_id, signature) in reverted(candidates, &landed) {
+                warn!("{} Slot {} was abandoned, reverting {}", icon("⏪"), slot, signature);
+                Metrics::inc(&state.metrics.reverted_events_total);
+                state.publish(
+                    program_id.as_ref(),
+                    EventRecord {
+                        event: WebEvent::Reverted { signature, slot },
+                        slot: None,
+                        signature: None,
+                        block_time: None,
+                        tx_index: None,
+                        log_index: None,
+                        pda_valid: None,
+                        finality: None,
+                        raw_base64: None,
+                        received_at: None,
+                        source: None,
+                        seq: 0,
+                    },
+                );
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(slot: u64, signature: &str, finality: Finality) -> EventRecord {
+        EventRecord {
+            slot: Some(slot),
+            signature: Some(signature.to_string()),
+            finality: Some(finality),
+            ..EventRecord::test(WebEvent::OrderAccepted {
+                order: "o1".to_string(),
+                courier: "k1".to_string(),
+            })
+        }
+    }
+
+    #[test]
+    fn only_events_from_unfinalized_slots_are_reverted() {
+        let watch = RollbackWatch::default();
+        let program_id = Pubkey::new_unique();
+        watch.apply(Some(&program_id), &record(10, "a", Finality::Confirmed));
+        // Two events of one transaction revert it once.
+        watch.apply(Some(&program_id), &record(11, "b", Finality::Confirmed));
+        watch.apply(Some(&program_id), &record(11, "b", Finality::Confirmed));
+        watch.apply(Some(&program_id), &record(12, "c", Finality::Finalized));
+        watch.apply(Some(&program_id), &record(13, "d", Finality::Confirmed));
+
+        let due = watch.due(12);
+        assert_eq!(due.keys().copied().collect::<Vec<_>>(), [10, 11]);
+        let finalized = HashSet::from([10, 12]);
+        assert_eq!(abandoned(due, &finalized), [(11, Some(program_id), "b".to_string())]);
+
+        // Slot 13 is past the root and still pending.
+        assert_eq!(watch.due(20).keys().copied().collect::<Vec<_>>(), [13]);
+    }
+
+    #[test]
+    fn transactions_that_landed_in_another_slot_are_not_reverted() {
+        let abandoned = vec![(11, None, "moved".to_string()), (11, None, "gone".to_string())];
+        let landed = HashMap::from([("moved".to_string(), 14)]);
+        assert_eq!(reverted(abandoned, &landed), [(11, None, "gone".to_string())]);
+    }
+}
@@ -0,0 +1,1409 @@
+use crate::{
+    admin,
+    channel::{Channel, Delivery, Subscription},
+    config::{Config, Role, RoutePolicy, ThrottlePolicy},
+    encode::{self, Encoding, Protocol},
+    log_style::icon,
+    events::{EventKind, EventRecord, EventSource, WebEvent},
+    listener,
+    metrics::{ClientGuard, Metrics},
+    leaderboard::LeaderboardEntry,
+    orders::OrderStates,
+    proto,
+    replay::{Backlog, Cursor},
+    shutdown::{self, ShutdownReason},
+    state::AppState,
+};
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post, MethodRouter},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    net::TcpListener,
+    time::{interval_at, sleep, MissedTickBehavior},
+};
+use tracing::{info, warn};
+
+//
+// ---------------- Per-connection control messages
+//
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+enum ControlMessage {
+    /// Events dropped from this client's queue under `LAG_POLICY=drop_oldest`.
+    Dropped { count: u64 },
+    /// Events this client fell behind the broadcast buffer by, under
+    /// `LAG_POLICY=disconnect`.
+    Lagged { count: u64 },
+    /// Events skipped under `WS_THROTTLE_POLICY=drop` since the last notice.
+    Throttled { dropped: u64 },
+    /// `MAX_REPLAY` cut the requested backlog; replay starts at `from_seq`.
+    ReplayTruncated { dropped: usize, from_seq: Option<u64> },
+    /// Reply to the `leaderboard` command.
+    Leaderboard { entries: Vec<LeaderboardEntry> },
+    /// Reply to the `stats` command.
+    ConnStats(ConnStats),
+    /// A client message that isn't a valid command.
+    Error { message: String },
+}
+
+/// Requests a client can send over its socket.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum Command {
+    Leaderboard { limit: Option<usize> },
+    Stats,
+}
+
+/// Delivery counters of one connection.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+struct ConnStats {
+    /// Events sent, replayed ones included.
+    sent: u64,
+    /// Events this client missed by falling behind.
+    lagged: u64,
+    /// Of `lagged`, those within the last `LAG_WINDOW_SECS`, which count
+    /// against `MAX_LAG_EVENTS`; `null` without it.
+    lagged_in_window: Option<u64>,
+    /// Events skipped by `WS_MAX_MSGS_PER_SEC`.
+    throttled: u64,
+    /// `seq` of the first event sent; `null` until then.
+    since_seq: Option<u64>,
+}
+
+impl ConnStats {
+    fn sent(&mut self, seq: u64) {
+        self.sent += 1;
+        self.since_seq.get_or_insert(seq);
+    }
+}
+
+/// How often a throttled client is told what it missed.
+const THROTTLE_NOTICE_EVERY: Duration = Duration::from_secs(1);
+
+/// `WS_MAX_MSGS_PER_SEC` for one connection: a token bucket with a second's
+/// worth of burst.
+struct Throttle {
+    rate: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl Throttle {
+    fn new(rate: u32) -> Self {
+        let rate = rate as f64;
+        Self {
+            rate,
+            tokens: rate,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Takes a token if there is one; otherwise says how long until there is.
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.refilled_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+
+    async fn wait(&mut self) {
+        while let Err(wait) = self.take(Instant::now()) {
+            sleep(wait).await;
+        }
+    }
+}
+
+/// `MAX_LAG_EVENTS` for one connection: what it missed over the last
+/// `LAG_WINDOW_SECS`, so a briefly slow client is let off and one that keeps
+/// falling behind is dropped.
+struct LagBudget {
+    max: u64,
+    window: Duration,
+    missed: VecDeque<(Instant, u64)>,
+}
+
+impl LagBudget {
+    fn new(max: u64, window: Duration) -> Self {
+        Self {
+            max,
+            window,
+            missed: VecDeque::new(),
+        }
+    }
+
+    /// Records `n` missed events; `Err` with the window's total once it is
+    /// over the budget.
+    fn miss(&mut self, n: u64, now: Instant) -> Result<(), u64> {
+        self.missed.push_back((now, n));
+        match self.in_window(now) {
+            total if total > self.max => Err(total),
+            _ => Ok(()),
+        }
+    }
+
+    fn in_window(&mut self, now: Instant) -> u64 {
+        while self
+            .missed
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > self.window)
+        {
+            self.missed.pop_front();
+        }
+        self.missed.iter().map(|(_, n)| n).sum()
+    }
+}
+
+const DEFAULT_LEADERBOARD_LIMIT: usize = 10;
+const MAX_LEADERBOARD_LIMIT: usize = 100;
+
+fn leaderboard(state: &AppState, limit: Option<usize>) -> Vec<LeaderboardEntry> {
+    let limit = limit
+        .unwrap_or(DEFAULT_LEADERBOARD_LIMIT)
+        .min(MAX_LEADERBOARD_LIMIT);
+    state.leaderboard.top(limit)
+}
+
+fn reply(text: &str, state: &AppState, stats: ConnStats) -> ControlMessage {
+    match serde_json::from_str(text) {
+        Ok(Command::Leaderboard { limit }) => ControlMessage::Leaderboard {
+            entries: leaderboard(state, limit),
+        },
+        Ok(Command::Stats) => ControlMessage::ConnStats(stats),
+        Err(e) => ControlMessage::Error {
+            message: format!("invalid command: {}", e),
+        },
+    }
+}
+
+//
+// ---------------- WebSocket handler
+//
+#[derive(Debug, Clone, Copy, Default)]
+struct EventFilter {
+    kind: Option<EventKind>,
+    min_amount: Option<u64>,
+}
+
+impl EventFilter {
+    fn matches(&self, event: &WebEvent, orders: &OrderStates) -> bool {
+        self.kind.is_none_or(|k| event.kind() == k) && self.min_amount.is_none_or(|min| {
+            // `OrderAccepted` carries no amount; use the tracked one, and let
+            // it through when the order's creation wasn't seen.
+            event
+                .amount()
+                .or_else(|| orders.amount(event.order()))
+                .is_none_or(|amount| amount >= min)
+        })
+    }
+}
+
+/// Query parameters accepted by every WS route, within its policy.
+#[derive(Debug, Default, Deserialize)]
+struct SocketParams {
+    /// `?include_raw=1`: add the original base64 event data as `raw_base64`.
+    include_raw: Option<String>,
+    /// `?min_amount=N`: only events of orders worth at least `N`.
+    min_amount: Option<u64>,
+    /// `?since_slot=N`: first replay buffered events with `slot > N`.
+    since_slot: Option<u64>,
+    /// `?since_seq=N`: first replay buffered events with `seq > N`.
+    since_seq: Option<u64>,
+    /// `?since_signature=S`: first replay buffered events after transaction `S`.
+    since_signature: Option<String>,
+    /// `?backfill=N`: first replay the last `N` buffered events that pass the filters.
+    backfill: Option<usize>,
+    /// `?pretty=1`: indented JSON frames.
+    pretty: Option<String>,
+    /// `?fields=a,b`: send only these event fields.
+    fields: Option<String>,
+    /// `?encoding=protobuf`: order events as protobuf Binary frames.
+    #[serde(default)]
+    encoding: Encoding,
+    /// Negotiated from the request headers, not the query.
+    #[serde(skip)]
+    protocol: Protocol,
+    /// Of the route the client connected to.
+    #[serde(skip)]
+    policy: Arc<RoutePolicy>,
+}
+
+impl SocketParams {
+    fn options(&self) -> encode::Options {
+        let enabled = |flag: &Option<String>| matches!(flag.as_deref(), Some("1" | "true"));
+        let requested: Option<Vec<String>> = self.fields.as_ref().map(|fields| {
+            fields
+                .split(',')
+                .map(|field| field.trim().to_string())
+                .filter(|field| !field.is_empty())
+                .collect()
+        });
+        let fields = match (&self.policy.fields, requested) {
+            (Some(allowed), Some(mut requested)) => {
+                requested.retain(|field| allowed.contains(field));
+                Some(requested)
+            }
+            (allowed, requested) => requested.or_else(|| allowed.clone()),
+        };
+        encode::Options {
+            include_raw: self.policy.include_raw || enabled(&self.include_raw),
+            pretty: enabled(&self.pretty),
+            fields,
+            protocol: self.protocol,
+            encoding: self.encoding,
+        }
+    }
+
+    fn filter(&self) -> EventFilter {
+        EventFilter {
+            kind: self.policy.kind,
+            min_amount: self.min_amount.max(self.policy.min_amount),
+        }
+    }
+
+    fn cursor(&self) -> Option<Cursor> {
+        if let Some(seq) = self.since_seq {
+            Some(Cursor::Seq(seq))
+        } else if let Some(signature) = &self.since_signature {
+            Some(Cursor::Signature(signature.clone()))
+        } else {
+            self.since_slot.map(Cursor::Slot)
+        }
+    }
+
+    fn backfill(&self) -> Option<usize> {
+        self.backfill.filter(|n| *n > 0)
+    }
+}
+
+const RETRY_AFTER_SECS: &str = "5";
+
+/// Why a WebSocket upgrade was refused. Sent as a JSON body,
+/// `{"reason":"capacity","message":"…"}`, next to the HTTP status.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Rejection {
+    /// 503: `MAX_CONNECTIONS` reached; retry after `Retry-After`.
+    Capacity,
+    /// 404: `/ws/:program_id` names a program that isn't watched.
+    UnknownProgram,
+    /// 400: a `?since_*` cursor or `?backfill` with the replay buffer disabled.
+    ReplayUnavailable,
+    /// 400: `?backfill` together with a `?since_*` cursor.
+    ConflictingReplay,
+    /// 410: events after the `?since_seq`/`?since_signature` cursor are no
+    /// longer (or not) buffered.
+    CursorExpired,
+    /// 400: the requested protocol version isn't one this server speaks.
+    UnsupportedProtocol,
+    /// 401: the route's policy wants a bearer token the request lacks.
+    Unauthorized,
+    /// 503: this instance is a standby that hasn't been promoted.
+    Standby,
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            Rejection::Capacity => (StatusCode::SERVICE_UNAVAILABLE, "too many connections"),
+            Rejection::UnknownProgram => (StatusCode::NOT_FOUND, "unknown program"),
+            Rejection::ReplayUnavailable => (
+                StatusCode::BAD_REQUEST,
+                "replay needs REPLAY_BUFFER_SIZE > 0",
+            ),
+            Rejection::ConflictingReplay => (
+                StatusCode::BAD_REQUEST,
+                "backfill can't be combined with a since_* cursor",
+            ),
+            Rejection::CursorExpired => {
+                (StatusCode::GONE, "cursor is outside the replay buffer")
+            }
+            Rejection::UnsupportedProtocol => (
+                StatusCode::BAD_REQUEST,
+                "unsupported protocol version, this server speaks bds-v1 and bds-v2",
+            ),
+            Rejection::Unauthorized => (
+                StatusCode::UNAUTHORIZED,
+                "this route needs Authorization: Bearer <token>",
+            ),
+            Rejection::Standby => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "standby instance, connect to the primary",
+            ),
+        };
+        let body = Json(serde_json::json!({ "reason": self, "message": message }));
+
+        match self {
+            Rejection::Capacity | Rejection::Standby => {
+                (status, [(header::RETRY_AFTER, RETRY_AFTER_SECS)], body).into_response()
+            }
+            Rejection::UnknownProgram
+            | Rejection::ReplayUnavailable
+            | Rejection::ConflictingReplay
+            | Rejection::CursorExpired
+            | Rejection::UnsupportedProtocol
+            | Rejection::Unauthorized => (status, body).into_response(),
+        }
+    }
+}
+
+const PROTOCOL_HEADER: &str = "x-bds-protocol";
+
+/// The protocol version a client asked for: `X-BDS-Protocol` if present,
+/// else the first `bds-*` entry of `Sec-WebSocket-Protocol` this server
+/// speaks (also returned, to be echoed back as the handshake requires).
+/// Clients naming neither get v1.
+fn negotiate(headers: &HeaderMap) -> Result<(Protocol, Option<Protocol>), Rejection> {
+    if let Some(value) = headers.get(PROTOCOL_HEADER) {
+        let protocol = value.to_str().ok().and_then(|v| v.trim().parse().ok());
+        return protocol.map(|p| (p, None)).ok_or(Rejection::UnsupportedProtocol);
+    }
+
+    let offered: Vec<&str> = headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|name| name.starts_with("bds-"))
+        .collect();
+    if offered.is_empty() {
+        return Ok((Protocol::default(), None));
+    }
+    offered
+        .iter()
+        .find_map(|name| name.parse().ok())
+        .map(|p| (p, Some(p)))
+        .ok_or(Rejection::UnsupportedProtocol)
+}
+
+// Shared by every WS route: applies the route's policy and connection
+// gating before upgrading.
+fn upgrade(
+    ws: WebSocketUpgrade,
+    headers: &HeaderMap,
+    state: &AppState,
+    channel: &Channel,
+    program_id: Option<&Pubkey>,
+    policy: Arc<RoutePolicy>,
+    params: SocketParams,
+) -> Response {
+    if state.is_standby() {
+        return Rejection::Standby.into_response();
+    }
+    if let Some(token) = &policy.token
+        && !admin::bearer_matches(headers, token)
+    {
+        return Rejection::Unauthorized.into_response();
+    }
+    let (protocol, subprotocol) = match negotiate(headers) {
+        Ok(negotiated) => negotiated,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let ws = match subprotocol {
+        Some(p) => ws.protocols([p.name()]),
+        None => ws,
+    };
+    let params = SocketParams {
+        protocol,
+        policy,
+        ..params
+    };
+
+    let cursor = params.cursor();
+    let backfill = params.backfill();
+    if cursor.is_some() && backfill.is_some() {
+        return Rejection::ConflictingReplay.into_response();
+    }
+    if (cursor.is_some() || backfill.is_some()) && !state.replay.is_enabled() {
+        return Rejection::ReplayUnavailable.into_response();
+    }
+
+    let Some(guard) =
+        ClientGuard::acquire(&state.metrics, state.config.max_connections)
+    else {
+        warn!(
+            "{} Connection cap of {:?} reached, refusing client",
+            icon("🚫"),
+            state.config.max_connections
+        );
+        return Rejection::Capacity.into_response();
+    };
+
+    let filter = params.filter();
+    // Subscribing before taking the backlog means nothing published in
+    // between is missed; live copies of replayed events are skipped by `seq`.
+    let sub = channel.subscribe();
+    let backlog = match backlog(state, program_id, cursor.as_ref(), backfill.is_some()) {
+        Ok(backlog) => backlog,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let state = state.clone();
+    ws.on_upgrade(move |socket| {
+        handle_socket(socket, state, sub, backlog, filter, params, guard)
+    })
+}
+
+/// What a client resuming from `cursor` or asking for `backfill` is sent
+/// before going live: the buffered events of the route's program, or of
+/// every program on the other routes, like the live stream.
+fn backlog(
+    state: &AppState,
+    program_id: Option<&Pubkey>,
+    cursor: Option<&Cursor>,
+    backfill: bool,
+) -> Result<Option<Backlog>, Rejection> {
+    let mut backlog = match cursor {
+        Some(cursor) => match state.replay.after(cursor, program_id) {
+            Some(backlog) => backlog,
+            None => return Err(Rejection::CursorExpired),
+        },
+        None if backfill => state.replay.all(program_id),
+        None => return Ok(None),
+    };
+    // Events held by `/admin/pause` are still to be sent live.
+    if let Some(seq) = state.pause.first_held_seq() {
+        backlog.withhold_from(seq);
+    }
+    Ok(Some(backlog))
+}
+
+const PROGRAM_ROUTE: &str = "/ws/:program_id";
+
+/// Every WS route and its policy: `/ws`, the typed `/ws/created` etc.,
+/// `/ws/:program_id`, then the `WS_ROUTES` ones.
+fn route_policies(config: &Config) -> Vec<RoutePolicy> {
+    let typed = |path: &str, kind| RoutePolicy {
+        kind: Some(kind),
+        ..RoutePolicy::new(path)
+    };
+    [
+        RoutePolicy::new("/ws"),
+        typed("/ws/created", EventKind::OrderCreated),
+        typed("/ws/accepted", EventKind::OrderAccepted),
+        typed("/ws/completed", EventKind::OrderCompleted),
+        RoutePolicy::new(PROGRAM_ROUTE),
+    ]
+    .into_iter()
+    .chain(config.ws_routes.iter().cloned())
+    .collect()
+}
+
+// A route on the shared broadcast channel.
+fn ws_route(policy: RoutePolicy) -> MethodRouter<AppState> {
+    let policy = Arc::new(policy);
+    get(
+        move |ws: WebSocketUpgrade,
+              headers: HeaderMap,
+              State(state): State<AppState>,
+              Query(params): Query<SocketParams>| async move {
+            upgrade(ws, &headers, &state, &state.channel, None, policy, params)
+        },
+    )
+}
+
+// `/ws/:program_id`: the program's own channel.
+fn program_ws_route(policy: RoutePolicy) -> MethodRouter<AppState> {
+    let policy = Arc::new(policy);
+    get(
+        move |ws: WebSocketUpgrade,
+              headers: HeaderMap,
+              State(state): State<AppState>,
+              Path(program_id): Path<String>,
+              Query(params): Query<SocketParams>| async move {
+            let program = program_id
+                .parse::<Pubkey>()
+                .ok()
+                .and_then(|id| Some((id, state.program_channels.get(&id)?)));
+
+            let Some((program_id, channel)) = program else {
+                return Rejection::UnknownProgram.into_response();
+            };
+
+            upgrade(ws, &headers, &state, channel, Some(&program_id), policy, params)
+        },
+    )
+}
+
+async fn handle_socket(
+    mut socket: WebSocket,
+    state: AppState,
+    mut sub: Subscription,
+    backlog: Option<Backlog>,
+    filter: EventFilter,
+    params: SocketParams,
+    _guard: ClientGuard,
+) {
+    info!("{} Web client connected", icon("🌐"));
+    let options = params.options();
+    let mut stats = ConnStats::default();
+
+    let mut replayed_up_to = 0;
+    if let Some(mut backlog) = backlog {
+        replayed_up_to = backlog.last_seq;
+
+        let max = state.config.max_replay.unwrap_or(usize::MAX);
+        if let Some(n) = params.backfill() {
+            // The client asked for the latest N only, so nothing to report.
+            backlog
+                .events
+                .retain(|record| filter.matches(&record.event, &state.orders));
+            backlog.cap(n.min(max));
+        }
+        let dropped = backlog.cap(max);
+        if dropped > 0 {
+            let notice = ControlMessage::ReplayTruncated {
+                dropped,
+                from_seq: backlog.events.first().map(|e| e.seq),
+            };
+            let json = encoded(
+                control_text(&notice, &state, options.pretty),
+                || "ReplayTruncated notice".to_string(),
+                &state.metrics,
+                options.pretty,
+            );
+            if socket.send(Message::Text(json.unwrap_or_else(|e| e))).await.is_err() {
+                warn!("{} Web client disconnected", icon("❌"));
+                return;
+            }
+        }
+
+        for record in backlog.events {
+            if !filter.matches(&record.event, &state.orders) {
+                continue;
+            }
+            let (frame, delivered) = event_frame(&record, &state, &options);
+            if socket.send(frame).await.is_err() {
+                warn!("{} Web client disconnected", icon("❌"));
+                return;
+            }
+            if delivered {
+                stats.sent(record.seq);
+            }
+        }
+    }
+
+    let mut throttle = state.config.ws_max_msgs_per_sec.map(Throttle::new);
+    // Dropped by the throttle and not reported yet.
+    let mut throttled = 0;
+    let mut throttle_notice = interval_at(
+        tokio::time::Instant::now() + THROTTLE_NOTICE_EVERY,
+        THROTTLE_NOTICE_EVERY,
+    );
+    throttle_notice.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut lag_budget = state
+        .config
+        .max_lag_events
+        .map(|max| LagBudget::new(max, state.config.lag_window));
+    let mut shutting_down = state.shutting_down.subscribe();
+
+    loop {
+        let delivery = tokio::select! {
+            delivery = sub.next() => delivery,
+            // The guard `wait_for` returns isn't `Send`; drop it right away.
+            _ = async { drop(shutting_down.wait_for(|closing| *closing).await) } => Delivery::Closed,
+            _ = throttle_notice.tick(), if throttled > 0 => {
+                let notice = ControlMessage::Throttled { dropped: std::mem::take(&mut throttled) };
+                let json = encoded(
+                    control_text(&notice, &state, options.pretty),
+                    || "Throttled notice".to_string(),
+                    &state.metrics,
+                    options.pretty,
+                );
+                if socket.send(Message::Text(json.unwrap_or_else(|e| e))).await.is_err() {
+                    warn!("{} Web client disconnected", icon("❌"));
+                    break;
+                }
+                continue;
+            }
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Text(text))) => {
+                    stats.lagged_in_window = lag_budget.as_mut().map(|b| b.in_window(Instant::now()));
+                    let json = encoded(
+                        control_text(&reply(&text, &state, stats), &state, options.pretty),
+                        || "command reply".to_string(),
+                        &state.metrics,
+                        options.pretty,
+                    );
+                    if socket.send(Message::Text(json.unwrap_or_else(|e| e))).await.is_err() {
+                        warn!("{} Web client disconnected", icon("❌"));
+                        break;
+                    }
+                    continue;
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+                    info!("{} Web client closed the connection", icon("👋"));
+                    break;
+                }
+                Some(Ok(_)) => continue,
+            },
+        };
+
+        // `seq` only for events that were delivered as such.
+        let (frame, seq) = match delivery {
+            Delivery::Event(record) => {
+                if record.seq <= replayed_up_to || !filter.matches(&record.event, &state.orders) {
+                    continue;
+                }
+                if let Some(throttle) = &mut throttle {
+                    match state.config.ws_throttle_policy {
+                        ThrottlePolicy::Buffer => throttle.wait().await,
+                        ThrottlePolicy::Drop => {
+                            if throttle.take(Instant::now()).is_err() {
+                                throttled += 1;
+                                stats.throttled += 1;
+                                Metrics::inc(&state.metrics.throttled_events_total);
+                                continue;
+                            }
+                        }
+                    }
+                }
+                let (frame, delivered) = event_frame(&record, &state, &options);
+                (frame, delivered.then_some(record.seq))
+            }
+            Delivery::Dropped(count) => {
+                stats.lagged += count;
+                if let Some(frame) = over_lag_budget(&mut lag_budget, count) {
+                    let _ = socket.send(Message::Close(Some(frame))).await;
+                    break;
+                }
+                let json = control_text(&ControlMessage::Dropped { count }, &state, options.pretty);
+                let json = encoded(json, || "Dropped notice".to_string(), &state.metrics, options.pretty);
+                (Message::Text(json.unwrap_or_else(|e| e)), None)
+            }
+            Delivery::Lagged(count) => {
+                stats.lagged += count;
+                if let Some(frame) = over_lag_budget(&mut lag_budget, count) {
+                    let _ = socket.send(Message::Close(Some(frame))).await;
+                    break;
+                }
+                let json = control_text(&ControlMessage::Lagged { count }, &state, options.pretty);
+                let json = encoded(json, || "Lagged notice".to_string(), &state.metrics, options.pretty);
+                (Message::Text(json.unwrap_or_else(|e| e)), None)
+            }
+            Delivery::Closed => {
+                info!("{} Server shutting down, disconnecting web client", icon("👋"));
+                let _ = socket.send(Message::Close(Some(shutdown_frame()))).await;
+                break;
+            }
+        };
+
+        if socket.send(frame).await.is_err() {
+            warn!("{} Web client disconnected", icon("❌"));
+            break;
+        }
+        if let Some(seq) = seq {
+            stats.sent(seq);
+        }
+    }
+}
+
+/// Counts `missed` against `MAX_LAG_EVENTS`; the Close frame to end the
+/// stream with once the budget is spent.
+fn over_lag_budget(budget: &mut Option<LagBudget>, missed: u64) -> Option<CloseFrame<'static>> {
+    let budget = budget.as_mut()?;
+    let total = budget.miss(missed, Instant::now()).err()?;
+    warn!(
+        "{} Web client missed {} events within {:?}, over MAX_LAG_EVENTS, disconnecting",
+        icon("❌"),
+        total,
+        budget.window
+    );
+    Some(CloseFrame {
+        code: close_code::POLICY,
+        reason: format!("lagged by {} events in {}s", total, budget.window.as_secs()).into(),
+    })
+}
+
+/// An event as a Binary protobuf frame under `?encoding=protobuf` when the
+/// schema covers it, as JSON text otherwise; `false` when encoding failed
+/// and an `Error` frame stands in for it.
+fn event_frame(record: &EventRecord, state: &AppState, options: &encode::Options) -> (Message, bool) {
+    if options.encoding == Encoding::Protobuf
+        && let Some(bytes) = proto::encode(record, options.include_raw)
+    {
+        return (Message::Binary(bytes), true);
+    }
+    let json = encoded(
+        encode::to_json(record, &state.config, options),
+        || describe(record),
+        &state.metrics,
+        options.pretty,
+    );
+    let delivered = json.is_ok();
+    (Message::Text(json.unwrap_or_else(|e| e)), delivered)
+}
+
+/// A control message as JSON text, with `JSON_U64_MODE` applied.
+fn control_text(message: &ControlMessage, state: &AppState, pretty: bool) -> serde_json::Result<String> {
+    encode::to_text(&encode::to_value(message, state.config.u64_mode)?, pretty)
+}
+
+/// `json`, or, when encoding failed, an `Error` frame in its place naming
+/// what was lost, so the client isn't left waiting for it. Encoding plain
+/// serde types only fails on a bug, so failures are logged and counted in
+/// `encode_failures_total`; the connection stays open.
+fn encoded(
+    json: serde_json::Result<String>,
+    what: impl FnOnce() -> String,
+    metrics: &Metrics,
+    pretty: bool,
+) -> Result<String, String> {
+    json.map_err(|e| {
+        let what = what();
+        Metrics::inc(&metrics.encode_failures_total);
+        warn!("{} Encoding {} failed: {}", icon("🧩"), what, e);
+        let notice = ControlMessage::Error {
+            message: format!("{} could not be encoded", what),
+        };
+        // Nothing in a string-only message can fail to encode.
+        encode::to_text(&notice, pretty).unwrap_or_default()
+    })
+}
+
+fn describe(record: &EventRecord) -> String {
+    format!("{:?} event seq {}", record.event.kind(), record.seq)
+}
+
+/// Close frame for streams ended by shutdown, so clients can tell it apart
+/// from a slow-consumer drop.
+fn shutdown_frame() -> CloseFrame<'static> {
+    CloseFrame {
+        code: close_code::AWAY,
+        reason: "server shutting down".into(),
+    }
+}
+
+//
+// ---------------- Event search (recent events only)
+//
+#[derive(Debug, Deserialize)]
+struct SearchQuery {
+    order: Option<String>,
+    customer: Option<String>,
+    courier: Option<String>,
+    order_id: Option<u64>,
+}
+
+impl SearchQuery {
+    fn matches(&self, event: &WebEvent) -> bool {
+        self.order.as_deref().is_none_or(|o| event.order() == o)
+            && self
+                .customer
+                .as_deref()
+                .is_none_or(|c| event.customer() == Some(c))
+            && self
+                .courier
+                .as_deref()
+                .is_none_or(|c| event.courier() == Some(c))
+            && self.order_id.is_none_or(|id| event.order_id() == Some(id))
+    }
+}
+
+// Scans only the in-memory replay buffer, so older events are not found.
+async fn search_handler(
+    State(state): State<AppState>,
+    Query(query): Query<SearchQuery>,
+) -> Response {
+    let events = state.replay.filter(|r| query.matches(&r.event));
+    json_response(&events, &state)
+}
+
+//
+// ---------------- Test-only event injection
+//
+// Broadcasts a `WebEvent` as if it had been decoded from the chain.
+#[cfg(feature = "test-inject")]
+async fn inject_handler(
+    State(state): State<AppState>,
+    Json(event): Json<WebEvent>,
+) -> StatusCode {
+    state.publish(
+        None,
+        EventRecord {
+            event,
+            slot: None,
+            signature: None,
+            block_time: None,
+            tx_index: None,
+            log_index: None,
+            pda_valid: None,
+            finality: None,
+            raw_base64: None,
+            received_at: None,
+            source: None,
+            seq: 0,
+        },
+    );
+    StatusCode::ACCEPTED
+}
+
+//
+// ---------------- External events (`INGEST_TOKEN`)
+//
+#[derive(Serialize)]
+struct IngestResult {
+    accepted: usize,
+    rejected: Vec<RejectedLine>,
+}
+
+#[derive(Serialize)]
+struct RejectedLine {
+    /// 1-based, counting blank lines.
+    line: usize,
+    error: String,
+}
+
+/// Newline-delimited `WebEvent` JSON from another system, published like
+/// decoded chain events and marked `"source":"external"`. Each line stands on
+/// its own: invalid ones are reported and the rest still go out, so a `422`
+/// must not be resent whole.
+async fn ingest_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    if !state
+        .config
+        .ingest_token
+        .as_ref()
+        .is_some_and(|token| admin::bearer_matches(&headers, token))
+    {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let mut result = IngestResult {
+        accepted: 0,
+        rejected: Vec::new(),
+    };
+    for (i, line) in body.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = match ingest_line(line) {
+            Ok(event) => event,
+            Err(error) => {
+                Metrics::inc(&state.metrics.ingest_rejected_lines_total);
+                result.rejected.push(RejectedLine { line: i + 1, error });
+                continue;
+            }
+        };
+        if let Some(allowed) = &state.config.party_allowlist
+            && !listener::involves_party(&event, allowed, &state.orders)
+        {
+            Metrics::inc(&state.metrics.party_filtered_events_total);
+            result.accepted += 1;
+            continue;
+        }
+        state.publish(
+            None,
+            EventRecord {
+                event,
+                slot: None,
+                signature: None,
+                block_time: None,
+                tx_index: None,
+                log_index: None,
+                pda_valid: None,
+                finality: None,
+                raw_base64: None,
+                received_at: None,
+                source: Some(EventSource::External),
+                seq: 0,
+            },
+        );
+        Metrics::inc(&state.metrics.ingested_events_total);
+        result.accepted += 1;
+    }
+
+    let status = if result.rejected.is_empty() {
+        StatusCode::OK
+    } else {
+        StatusCode::UNPROCESSABLE_ENTITY
+    };
+    (status, Json(result)).into_response()
+}
+
+/// Only order events, with valid pubkeys; the service's own events are not
+/// for others to send.
+fn ingest_line(line: &str) -> Result<WebEvent, String> {
+    let event: WebEvent = serde_json::from_str(line).map_err(|e| e.to_string())?;
+    if event.is_own() {
+        return Err(format!("{:?} events cannot be ingested", event.kind()));
+    }
+    for key in [Some(event.order()), event.customer(), event.courier()]
+        .into_iter()
+        .flatten()
+    {
+        if key.parse::<Pubkey>().is_err() {
+            return Err(format!("{} is not a valid pubkey", key));
+        }
+    }
+    Ok(event)
+}
+
+//
+// ---------------- Order queries
+//
+async fn in_flight_handler(State(state): State<AppState>) -> Response {
+    json_response(&state.orders.in_flight(), &state)
+}
+
+/// Every buffered event of one order, oldest first. `id` is the order
+/// account, or a numeric `order_id`, which stands for the accounts of the
+/// events carrying it.
+async fn order_history_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Response {
+    let orders: Vec<String> = match id.parse::<u64>() {
+        Ok(order_id) => {
+            let mut orders: Vec<String> = state
+                .replay
+                .filter(|r| r.event.order_id() == Some(order_id))
+                .into_iter()
+                .map(|r| r.event.order().to_string())
+                .collect();
+            orders.sort();
+            orders.dedup();
+            orders
+        }
+        Err(_) => vec![id],
+    };
+    let events = state
+        .replay
+        .filter(|r| !r.event.is_own() && orders.iter().any(|o| o == r.event.order()));
+    if events.is_empty() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    json_response(&events, &state)
+}
+
+#[derive(Debug, Deserialize)]
+struct LeaderboardQuery {
+    limit: Option<usize>,
+}
+
+async fn leaderboard_handler(
+    State(state): State<AppState>,
+    Query(query): Query<LeaderboardQuery>,
+) -> Response {
+    json_response(&leaderboard(&state, query.limit), &state)
+}
+
+/// REST bodies go through the same u64 encoding as streamed events.
+fn json_response<T: Serialize>(body: &T, state: &AppState) -> Response {
+    match encode::to_value(body, state.config.u64_mode) {
+        Ok(value) => Json(value).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+//
+// ---------------- Health / metrics
+//
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    let live = state.live_subscriptions.load(Ordering::Relaxed);
+    if state.is_standby() {
+        (StatusCode::SERVICE_UNAVAILABLE, "standby")
+    } else if live == state.config.live_subscription_target() {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "subscription not ready")
+    }
+}
+
+async fn metrics_handler(State(state): State<AppState>) -> String {
+    state.metrics.render(state.broadcast_lag())
+}
+
+#[derive(Debug, Serialize)]
+struct Stats {
+    live_subscriptions: usize,
+    /// Listeners waiting for a `MAX_SUBSCRIPTIONS` slot.
+    queued_subscriptions: usize,
+    subscription_uptime_seconds: u64,
+    reconnect_count: u64,
+    connected_clients: u64,
+    /// `standby` until `POST /admin/promote`.
+    role: Role,
+    paused: bool,
+    /// In-memory buffers turned off by configuration, e.g. under `LOW_MEMORY`.
+    disabled_buffers: Vec<&'static str>,
+    /// Every WS route with its policy.
+    routes: Vec<RoutePolicy>,
+}
+
+fn disabled_buffers(config: &Config) -> Vec<&'static str> {
+    [
+        ("replay", config.replay_buffer_size == 0),
+        ("dead_letters", config.dead_letter_buffer_size == 0),
+        ("pause", config.pause_buffer_size == 0),
+        ("orders", config.low_memory),
+        ("leaderboard", config.low_memory),
+    ]
+    .into_iter()
+    .filter_map(|(name, disabled)| disabled.then_some(name))
+    .collect()
+}
+
+async fn stats_handler(State(state): State<AppState>) -> Response {
+    let metrics = &state.metrics;
+    let stats = Stats {
+        live_subscriptions: state.live_subscriptions.load(Ordering::Relaxed),
+        queued_subscriptions: state.subscription_slots.waiting(),
+        subscription_uptime_seconds: metrics.subscription_uptime_seconds(),
+        reconnect_count: metrics.reconnect_count.load(Ordering::Relaxed),
+        connected_clients: metrics.connected_clients.load(Ordering::Relaxed),
+        role: if state.is_standby() {
+            Role::Standby
+        } else {
+            Role::Primary
+        },
+        paused: state.pause.is_paused(),
+        disabled_buffers: disabled_buffers(&state.config),
+        routes: route_policies(&state.config),
+    };
+    json_response(&stats, &state)
+}
+
+#[derive(Debug, Serialize)]
+struct ProgramStats {
+    program_id: String,
+    matched: u64,
+    unknown_discriminator: u64,
+    decode_failures: u64,
+    /// Decoded events by type.
+    events: BTreeMap<String, u64>,
+    /// Unix seconds of the latest decoded event.
+    last_seen_at: Option<u64>,
+    /// Subscriptions up, of `subscriptions` wanted.
+    live_subscriptions: usize,
+    subscriptions: usize,
+}
+
+/// Decode health per watched program, in `PROGRAM_ID` order.
+async fn program_stats_handler(State(state): State<AppState>) -> Response {
+    let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+    let stats = state
+        .program_stats
+        .iter()
+        .map(|(id, counters)| ProgramStats {
+            program_id: id.to_string(),
+            matched: load(&counters.matched),
+            unknown_discriminator: load(&counters.unknown_discriminator),
+            decode_failures: load(&counters.decode_failures),
+            events: EventKind::ALL
+                .iter()
+                .map(|kind| (format!("{:?}", kind), load(&counters.events[kind.code() as usize])))
+                .collect(),
+            last_seen_at: Some(load(&counters.last_seen_at)).filter(|t| *t != 0),
+            live_subscriptions: counters.live_subscriptions.load(Ordering::Relaxed),
+            subscriptions: state.config.finality_levels(id).len(),
+        })
+        .collect::<Vec<_>>();
+    json_response(&stats, &state)
+}
+
+//
+// ---------------- HTTP / WS server
+//
+/// Every HTTP and WebSocket route, as configured.
+fn app(state: &AppState) -> Router {
+    let app = route_policies(&state.config)
+        .into_iter()
+        .fold(Router::new(), |app, policy| {
+            let path = policy.path.clone();
+            match path.as_str() {
+                PROGRAM_ROUTE => app.route(&path, program_ws_route(policy)),
+                _ => app.route(&path, ws_route(policy)),
+            }
+        })
+        .route("/events/search", get(search_handler))
+        .route("/orders/in-flight", get(in_flight_handler))
+        .route("/orders/:id/history", get(order_history_handler))
+        .route("/couriers/leaderboard", get(leaderboard_handler))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics_handler))
+        .route("/stats", get(stats_handler))
+        .route("/stats/programs", get(program_stats_handler));
+
+    let app = match state.config.ingest_token {
+        Some(_) => app.route("/ingest", post(ingest_handler)),
+        None => app,
+    };
+
+    #[cfg(feature = "test-inject")]
+    let app = {
+        warn!(
+            "{} test-inject enabled: POST /test/inject broadcasts arbitrary events",
+            icon("⚠️")
+        );
+        app.route("/test/inject", axum::routing::post(inject_handler))
+    };
+
+    let app = match state.config.admin_token {
+        Some(_) => app.merge(admin::router(state)),
+        None => app,
+    };
+
+    app.with_state(state.clone())
+}
+
+/// Serves HTTP/WS on `0.0.0.0:3000` in a background task.
+pub async fn start_server(state: &AppState) {
+    let app = app(state);
+
+    tokio::spawn(async move {
+        let listener = TcpListener::bind("0.0.0.0:3000")
+            .await
+            .unwrap_or_else(|e| {
+                shutdown::exit(ShutdownReason::FatalError, &format!("cannot bind 0.0.0.0:3000: {}", e))
+            });
+
+        info!("{} WebSocket server on ws://localhost:3000/ws", icon("🚀"));
+
+        if let Err(e) = axum::serve(listener, app).await {
+            shutdown::exit(ShutdownReason::FatalError, &format!("server failed: {}", e));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use futures_util::StreamExt;
+    use serde_json::json;
+    use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
+
+    fn record() -> EventRecord {
+        EventRecord::test(WebEvent::OrderAccepted {
+            order: "order".to_string(),
+            courier: "courier".to_string(),
+        })
+    }
+
+    /// Serves `state`'s routes on a free local port; the `/ws` URL.
+    async fn serve(state: &AppState) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+        let app = app(state);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    #[tokio::test]
+    async fn shutdown_closes_open_sockets_with_1001() {
+        let state = AppState::new(&Config::from_pairs(&[]));
+        let (mut ws, _) = connect_async(serve(&state).await).await.unwrap();
+        state.shutting_down.send_replace(true);
+
+        let Some(Ok(WsMessage::Close(Some(frame)))) = ws.next().await else {
+            panic!("expected a Close frame");
+        };
+        assert_eq!(u16::from(frame.code), close_code::AWAY);
+        assert_eq!(frame.reason, "server shutting down");
+    }
+
+    #[tokio::test]
+    async fn lagged_client_is_told_and_keeps_streaming() {
+        let state = AppState::new(&Config::from_pairs(&[]));
+        let (mut ws, _) = connect_async(serve(&state).await).await.unwrap();
+        // Published without yielding, so the socket task falls behind the
+        // 128 events the broadcast buffer holds.
+        for i in 0..200 {
+            state.publish(None, EventRecord { slot: Some(i), ..record() });
+        }
+
+        let mut next = async || match ws.next().await {
+            Some(Ok(WsMessage::Text(text))) => serde_json::from_str::<serde_json::Value>(&text).unwrap(),
+            other => panic!("expected a text frame, got {:?}", other),
+        };
+        let notice = next().await;
+        assert_eq!((notice["type"].as_str(), notice["count"].as_u64()), (Some("Lagged"), Some(72)));
+        for slot in 72..200 {
+            assert_eq!(next().await["slot"], slot);
+        }
+    }
+
+    #[test]
+    fn encoding_failures_become_counted_error_frames() {
+        let metrics = Metrics::default();
+        let json = encode::to_json(&record(), &Config::from_pairs(&[]), &Default::default());
+        assert!(encoded(json, || describe(&record()), &metrics, false).is_ok());
+
+        // serde_json refuses non-string map keys.
+        let failing = serde_json::to_string(&std::collections::HashMap::from([((1, 2), 3)]));
+        let frame = encoded(failing, || describe(&record()), &metrics, false).unwrap_err();
+        assert_eq!(
+            frame,
+            r#"{"type":"Error","message":"OrderAccepted event seq 0 could not be encoded"}"#
+        );
+        assert_eq!(metrics.encode_failures_total.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn route_policies_bound_client_params() {
+        let config = Config::from_pairs(&[
+            ("WS_ROUTES", "public:min_amount=100:fields=order,amount;internal:include_raw:auth"),
+            ("WS_TOKEN_INTERNAL", "s3cret"),
+        ]);
+        let routes = route_policies(&config);
+        let (public, internal) = (&routes[routes.len() - 2], &routes[routes.len() - 1]);
+        assert_eq!((public.path.as_str(), internal.path.as_str()), ("/ws/public", "/ws/internal"));
+        assert!(internal.include_raw && internal.token.as_deref() == Some("s3cret"));
+
+        let policy = Arc::new(public.clone());
+        let params = |fields: Option<&str>, min_amount| SocketParams {
+            fields: fields.map(str::to_string),
+            min_amount,
+            policy: policy.clone(),
+            ..SocketParams::default()
+        };
+        let fields = |params: SocketParams| params.options().fields.unwrap();
+        assert_eq!(fields(params(None, None)), ["order", "amount"]);
+        assert_eq!(fields(params(Some("amount,raw_base64"), None)), ["amount"]);
+        assert_eq!(params(None, Some(10)).filter().min_amount, Some(100));
+        assert_eq!(params(None, Some(500)).filter().min_amount, Some(500));
+    }
+
+    #[test]
+    fn throttle_refills_at_the_rate() {
+        let mut throttle = Throttle::new(2);
+        let now = throttle.refilled_at;
+        assert!(throttle.take(now).is_ok() && throttle.take(now).is_ok());
+        assert_eq!(throttle.take(now), Err(Duration::from_millis(500)));
+        assert!(throttle.take(now + Duration::from_millis(500)).is_ok());
+    }
+
+    #[test]
+    fn lag_budget_forgives_lag_outside_the_window() {
+        let mut budget = LagBudget::new(10, Duration::from_secs(60));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        assert_eq!(budget.miss(8, at(0)), Ok(()));
+        assert_eq!(budget.miss(8, at(61)), Ok(()));
+        assert_eq!(budget.miss(3, at(90)), Err(11));
+        assert_eq!(budget.in_window(at(200)), 0);
+    }
+
+    #[test]
+    fn ingest_accepts_only_valid_order_events() {
+        let key = "11111111111111111111111111111111";
+        let accepted = format!(r#"{{"type":"OrderAccepted","order":"{}","courier":"{}"}}"#, key, key);
+        assert!(ingest_line(&accepted).is_ok());
+        assert_eq!(
+            ingest_line(&accepted.replacen(key, "nope", 1)).unwrap_err(),
+            "nope is not a valid pubkey"
+        );
+        assert!(ingest_line(r#"{"type":"SourceReconnected","program_id":"p","gap_ms":1,"last_seq":0}"#).is_err());
+        assert!(ingest_line("{").is_err());
+    }
+
+    #[test]
+    fn program_backlog_holds_only_the_programs_events() {
+        let (a, b) = (
+            "AdScDF7jTLCmb3iP4ZPugb6kxDtix1U7pVRu99VDJwdy",
+            "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4",
+        );
+        let config = Config::from_pairs(&[("PROGRAM_ID", &format!("{},{}", a, b))]);
+        let state = AppState::new(&config);
+        let (a, b): (Pubkey, Pubkey) = (a.parse().unwrap(), b.parse().unwrap());
+        for (i, program_id) in [a, b, a, b].iter().enumerate() {
+            state.publish(
+                Some(program_id),
+                EventRecord {
+                    slot: Some(100 + i as u64),
+                    signature: Some(format!("sig{}", i)),
+                    ..record()
+                },
+            );
+        }
+        let signatures = |backlog: Option<Backlog>| -> Vec<String> {
+            backlog
+                .unwrap()
+                .events
+                .into_iter()
+                .map(|e| e.signature.unwrap())
+                .collect()
+        };
+
+        let backfill = backlog(&state, Some(&a), None, true).unwrap();
+        assert_eq!(signatures(backfill), ["sig0", "sig2"]);
+        let since_seq = backlog(&state, Some(&b), Some(&Cursor::Seq(0)), false).unwrap();
+        assert_eq!(signatures(since_seq), ["sig1", "sig3"]);
+        let since_slot = backlog(&state, Some(&a), Some(&Cursor::Slot(100)), false).unwrap();
+        assert_eq!(signatures(since_slot), ["sig2"]);
+        // A cursor from another program's event still places the resume point.
+        let cursor = Cursor::Signature("sig1".to_string());
+        let since_signature = backlog(&state, Some(&a), Some(&cursor), false).unwrap();
+        assert_eq!(signatures(since_signature), ["sig2"]);
+
+        // `/ws` replays every program.
+        let all = backlog(&state, None, Some(&Cursor::Seq(1)), false).unwrap();
+        assert_eq!(signatures(all), ["sig1", "sig2", "sig3"]);
+    }
+
+    #[test]
+    fn backlog_leaves_out_events_held_by_pause() {
+        let state = AppState::new(&Config::from_pairs(&[]));
+        let publish = |slot| state.publish(None, EventRecord { slot: Some(slot), ..record() });
+        publish(1);
+        state.pause.pause();
+        publish(2);
+        publish(3);
+
+        let backfill = backlog(&state, None, None, true).unwrap().unwrap();
+        assert_eq!(backfill.events.iter().map(|e| e.slot).collect::<Vec<_>>(), [Some(1)]);
+        assert_eq!(backfill.last_seq, 1);
+        assert!(backlog(&state, None, Some(&Cursor::Seq(1)), false).unwrap().unwrap().events.is_empty());
+
+        state.pause.resume(|item| state.broadcast(item));
+        assert_eq!(backlog(&state, None, None, true).unwrap().unwrap().events.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn u64_mode_covers_control_frames_and_stats() {
+        for (mode, from_seq, min_amount) in [("all_strings", json!("7"), json!("5")), ("none", json!(7), json!(5))] {
+            let config = Config::from_pairs(&[("JSON_U64_MODE", mode), ("WS_ROUTES", "big:min_amount=5")]);
+            let state = AppState::new(&config);
+
+            let notice = ControlMessage::ReplayTruncated { dropped: 1, from_seq: Some(7) };
+            let frame: serde_json::Value = serde_json::from_str(&control_text(&notice, &state, false).unwrap()).unwrap();
+            assert_eq!(frame["from_seq"], from_seq);
+
+            let body = stats_handler(State(state)).await.into_body();
+            let stats: serde_json::Value =
+                serde_json::from_slice(&axum::body::to_bytes(body, usize::MAX).await.unwrap()).unwrap();
+            let route = stats["routes"].as_array().unwrap().iter().find(|r| r["path"] == "/ws/big").unwrap();
+            assert_eq!(route["min_amount"], min_amount);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use crate::{log_style::icon, otlp, state::AppState};
+use std::{
+    fs, panic, process,
+    sync::{atomic::Ordering, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::{
+    runtime::{Handle, RuntimeFlavor},
+    signal::unix::{signal, SignalKind},
+    task,
+};
+use tracing::warn;
+
+/// How long `exit` waits for open sockets to take their Close frame.
+const CLOSE_GRACE: Duration = Duration::from_secs(2);
+
+/// The running service, for exits that don't have it at hand (the panic
+/// hook, the server task).
+static STATE: OnceLock<AppState> = OnceLock::new();
+
+//
+// ---------------- Shutdown
+//
+// Every way `run` ends goes through `exit`: a signal, an error the service
+// cannot continue after, or a panic on any thread. `exit` logs the reason in
+// one final line, exposes it as `shutdown_reason`, closes open WebSockets with
+// `1001`, pushes the reason to the OTLP collector when one is configured,
+// removes the `UDS_PATH` socket and exits with the reason's code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownReason {
+    SignalTerm,
+    SignalInt,
+    FatalError,
+    Panic,
+}
+
+impl ShutdownReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ShutdownReason::SignalTerm => "signal_term",
+            ShutdownReason::SignalInt => "signal_int",
+            ShutdownReason::FatalError => "fatal_error",
+            ShutdownReason::Panic => "panic",
+        }
+    }
+
+    /// SIGTERM is the orderly stop, so it exits `0`; SIGINT keeps the shell's
+    /// `130`, and a panic Rust's usual `101`.
+    fn exit_code(self) -> i32 {
+        match self {
+            ShutdownReason::SignalTerm => 0,
+            ShutdownReason::SignalInt => 130,
+            ShutdownReason::FatalError => 1,
+            ShutdownReason::Panic => 101,
+        }
+    }
+}
+
+/// Installs the panic hook and the signal handlers. Call once, from `run`.
+pub fn install(state: &AppState) {
+    let _ = STATE.set(state.clone());
+
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        // The message on the same line, not below as in `info`'s Display.
+        let message = info.payload_as_str().unwrap_or("Box<dyn Any>");
+        match info.location() {
+            Some(at) => exit(ShutdownReason::Panic, &format!("{} at {}", message, at)),
+            None => exit(ShutdownReason::Panic, message),
+        }
+    }));
+
+    tokio::spawn(async {
+        let (Ok(mut terminate), Ok(mut interrupt)) = (
+            signal(SignalKind::terminate()),
+            signal(SignalKind::interrupt()),
+        ) else {
+            warn!("{} Cannot install signal handlers", icon("⚠️"));
+            return;
+        };
+        let reason = tokio::select! {
+            _ = terminate.recv() => ShutdownReason::SignalTerm,
+            _ = interrupt.recv() => ShutdownReason::SignalInt,
+        };
+        exit(reason, "");
+    });
+}
+
+/// The single way out. A second caller (e.g. a panic during shutdown) exits
+/// right away with its own code, without repeating the cleanup.
+pub fn exit(reason: ShutdownReason, detail: &str) -> ! {
+    static EXITING: OnceLock<()> = OnceLock::new();
+    if EXITING.set(()).is_err() {
+        process::exit(reason.exit_code());
+    }
+
+    warn!(
+        "{} Shutting down: reason={} exit_code={}{}{}",
+        icon("🛑"),
+        reason.as_str(),
+        reason.exit_code(),
+        if detail.is_empty() { "" } else { " " },
+        detail
+    );
+    if let Some(state) = STATE.get() {
+        let _ = state.metrics.shutdown_reason.set(reason.as_str());
+        close_sockets(state);
+        otlp::push_final(state);
+        if let Some(path) = &state.config.uds_path {
+            let _ = fs::remove_file(path);
+        }
+    }
+    process::exit(reason.exit_code());
+}
+
+/// Tells every open WebSocket to close and waits up to `CLOSE_GRACE` for
+/// them to go.
+fn close_sockets(state: &AppState) {
+    state.shutting_down.send_replace(true);
+    let wait = || {
+        let started = Instant::now();
+        while state.metrics.connected_clients.load(Ordering::Relaxed) > 0
+            && started.elapsed() < CLOSE_GRACE
+        {
+            thread::sleep(Duration::from_millis(10));
+        }
+    };
+    // On a runtime worker, hand its other tasks (the sockets among them) off
+    // while waiting.
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => task::block_in_place(wait),
+        _ => wait(),
+    }
+}
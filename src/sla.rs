@@ -0,0 +1,192 @@
+use crate::{log_style::icon, state::AppState, EventRecord, WebEvent};
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+/// How often the rate is compared with the threshold.
+const CHECK_EVERY: Duration = Duration::from_secs(5);
+
+//
+// ---------------- Completion-rate SLA (`SLA_MIN_COMPLETIONS_PER_MIN`)
+//
+// Remembers when each `OrderCompleted` was published and periodically
+// computes completions per minute over the rolling window. An `SlaAlert` is
+// published once the rate has stayed under the threshold for the hold
+// period, and an `SlaRecovered` once it has stayed at or above it as long, so
+// a rate hovering around the threshold does not flap.
+pub struct SlaMonitor {
+    window: Duration,
+    completions: Mutex<VecDeque<Instant>>,
+}
+
+impl SlaMonitor {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            completions: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn apply(&self, record: &EventRecord) {
+        if let WebEvent::OrderCompleted { .. } = record.event {
+            self.completions.lock().unwrap().push_back(Instant::now());
+        }
+    }
+
+    /// Completions per minute over the window ending at `now`.
+    pub(crate) fn rate(&self, now: Instant) -> f64 {
+        let mut completions = self.completions.lock().unwrap();
+        while completions
+            .front()
+            .is_some_and(|at| now.duration_since(*at) > self.window)
+        {
+            completions.pop_front();
+        }
+        completions.len() as f64 * 60.0 / self.window.as_secs_f64()
+    }
+}
+
+/// Breach state that only flips once the opposite condition has held for
+/// `hold` without interruption.
+struct Hysteresis {
+    hold: Duration,
+    breached: bool,
+    /// Since when the condition has disagreed with `breached`.
+    pending_since: Option<Instant>,
+}
+
+impl Hysteresis {
+    fn new(hold: Duration) -> Self {
+        Self {
+            hold,
+            breached: false,
+            pending_since: None,
+        }
+    }
+
+    /// `Some(breached)` when the state flips.
+    fn update(&mut self, below: bool, now: Instant) -> Option<bool> {
+        if below == self.breached {
+            self.pending_since = None;
+            return None;
+        }
+        let since = *self.pending_since.get_or_insert(now);
+        if now.duration_since(since) < self.hold {
+            return None;
+        }
+        self.breached = below;
+        self.pending_since = None;
+        Some(below)
+    }
+}
+
+/// The first window is left to fill before the rate is judged, so a fresh
+/// start doesn't alert.
+pub fn spawn(state: AppState, threshold: f64, hold: Duration) {
+    let Some(monitor) = state.sla.clone() else {
+        return;
+    };
+    thread::spawn(move || {
+        let started = Instant::now();
+        let mut hysteresis = Hysteresis::new(hold);
+        loop {
+            thread::sleep(CHECK_EVERY);
+            let now = Instant::now();
+            let rate = monitor.rate(now);
+            if now.duration_since(started) < monitor.window {
+                continue;
+            }
+            let Some(breached) = hysteresis.update(rate < threshold, now) else {
+                continue;
+            };
+            let window_secs = monitor.window.as_secs();
+            let event = if breached {
+                warn!(
+                    "{} Completion rate {:.2}/min is under the SLA of {:.2}/min",
+                    icon("📉"),
+                    rate,
+                    threshold
+                );
+                WebEvent::SlaAlert {
+                    window_secs,
+                    rate,
+                    threshold,
+                }
+            } else {
+                info!(
+                    "{} Completion rate {:.2}/min is back within the SLA of {:.2}/min",
+                    icon("📈"),
+                    rate,
+                    threshold
+                );
+                WebEvent::SlaRecovered {
+                    window_secs,
+                    rate,
+                    threshold,
+                }
+            };
+            state.publish(
+                None,
+                EventRecord {
+                    event,
+                    slot: None,
+                    signature: None,
+                    block_time: None,
+                    tx_index: None,
+                    log_index: None,
+                    pda_valid: None,
+                    finality: None,
+                    raw_base64: None,
+                    received_at: None,
+                    source: None,
+                    seq: 0,
+                },
+            );
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flips_only_after_the_hold() {
+        let hold = Duration::from_secs(60);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut hysteresis = Hysteresis::new(hold);
+
+        assert_eq!(hysteresis.update(true, at(0)), None);
+        // A short recovery resets the clock.
+        assert_eq!(hysteresis.update(false, at(30)), None);
+        assert_eq!(hysteresis.update(true, at(40)), None);
+        assert_eq!(hysteresis.update(true, at(90)), None);
+        assert_eq!(hysteresis.update(true, at(100)), Some(true));
+        assert_eq!(hysteresis.update(true, at(200)), None);
+
+        assert_eq!(hysteresis.update(false, at(210)), None);
+        assert_eq!(hysteresis.update(false, at(270)), Some(false));
+    }
+
+    #[test]
+    fn rate_counts_completions_in_the_window() {
+        let monitor = SlaMonitor::new(Duration::from_secs(120));
+        let completed = EventRecord::test(WebEvent::OrderCompleted {
+            order: "o1".to_string(),
+            order_id: 1,
+            courier: "c1".to_string(),
+            amount: 1,
+        });
+        for _ in 0..3 {
+            monitor.apply(&completed);
+        }
+        let now = Instant::now();
+        assert_eq!(monitor.rate(now), 1.5);
+        assert_eq!(monitor.rate(now + Duration::from_secs(121)), 0.0);
+    }
+}
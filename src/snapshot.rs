@@ -0,0 +1,147 @@
+use crate::{
+    leaderboard::{CourierTotals, Leaderboard},
+    log_style::icon,
+    orders::{OrderState, OrderStates},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::Path, sync::Arc, thread, time::Duration};
+use tracing::{info, warn};
+
+/// Bumped whenever the snapshot layout changes; other versions are ignored.
+const VERSION: u32 = 1;
+
+//
+// ---------------- Projection snapshots (`SNAPSHOT_PATH`)
+//
+// The order-state projection and the leaderboard, written to disk
+// periodically and loaded at startup so a restart comes back warm. Writes go
+// to a temporary file first and are renamed into place, so a crash never
+// leaves a half-written snapshot behind.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    version: u32,
+    orders: HashMap<String, OrderState>,
+    couriers: HashMap<String, CourierTotals>,
+}
+
+impl Snapshot {
+    fn capture(orders: &OrderStates, leaderboard: &Leaderboard) -> Self {
+        Self {
+            version: VERSION,
+            orders: orders.snapshot(),
+            couriers: leaderboard.snapshot(),
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let bytes = bincode::serialize(self).map_err(|e| e.to_string())?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, bytes).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, path).map_err(|e| e.to_string())
+    }
+
+    /// `Ok(None)` when there is no snapshot yet.
+    fn load(path: &Path) -> Result<Option<Self>, String> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.to_string()),
+        };
+        let snapshot: Self = bincode::deserialize(&bytes).map_err(|e| e.to_string())?;
+        if snapshot.version != VERSION {
+            return Err(format!("unsupported snapshot version {}", snapshot.version));
+        }
+        Ok(Some(snapshot))
+    }
+}
+
+/// Loads the snapshot into the (empty) projections. A missing snapshot is
+/// normal on first start; an unreadable one is ignored with a warning.
+pub fn restore(path: &Path, orders: &OrderStates, leaderboard: &Leaderboard) {
+    match Snapshot::load(path) {
+        Ok(Some(snapshot)) => {
+            info!(
+                "{} Restored {} open orders and {} couriers from {}",
+                icon("💾"),
+                snapshot.orders.len(),
+                snapshot.couriers.len(),
+                path.display()
+            );
+            orders.restore(snapshot.orders);
+            leaderboard.restore(snapshot.couriers);
+        }
+        Ok(None) => {}
+        Err(e) => warn!(
+            "{} Ignoring snapshot {}: {}",
+            icon("⚠️"),
+            path.display(),
+            e
+        ),
+    }
+}
+
+pub fn spawn(
+    path: &Path,
+    interval: Duration,
+    orders: Arc<OrderStates>,
+    leaderboard: Arc<Leaderboard>,
+) {
+    let path = path.to_path_buf();
+    thread::spawn(move || {
+        loop {
+            thread::sleep(interval);
+            if let Err(e) = Snapshot::capture(&orders, &leaderboard).save(&path) {
+                warn!(
+                    "{} Writing snapshot {} failed: {}",
+                    icon("⚠️"),
+                    path.display(),
+                    e
+                );
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EventRecord, WebEvent};
+
+    fn record(event: WebEvent) -> EventRecord {
+        EventRecord {
+            block_time: Some(1),
+            ..EventRecord::test(event)
+        }
+    }
+
+    #[test]
+    fn round_trips_and_ignores_corrupt_files() {
+        let path = std::env::temp_dir().join(format!("snapshot-test-{}.bin", std::process::id()));
+        let (orders, leaderboard) = (OrderStates::default(), Leaderboard::default());
+        orders.apply(&record(WebEvent::OrderAccepted {
+            order: "o1".to_string(),
+            courier: "c1".to_string(),
+        }));
+        leaderboard.apply(&record(WebEvent::OrderCompleted {
+            order: "o0".to_string(),
+            order_id: 0,
+            courier: "c1".to_string(),
+            amount: 5,
+        }));
+        Snapshot::capture(&orders, &leaderboard).save(&path).unwrap();
+
+        let (orders, leaderboard) = (OrderStates::default(), Leaderboard::default());
+        restore(&path, &orders, &leaderboard);
+        assert_eq!(orders.in_flight().len(), 1);
+        assert_eq!(leaderboard.top(10)[0].volume, 5);
+
+        fs::write(&path, b"not a snapshot").unwrap();
+        assert!(Snapshot::load(&path).is_err());
+        let (orders, leaderboard) = (OrderStates::default(), Leaderboard::default());
+        restore(&path, &orders, &leaderboard);
+        assert!(orders.in_flight().is_empty());
+
+        fs::remove_file(&path).unwrap();
+        assert!(Snapshot::load(&path).unwrap().is_none());
+    }
+}
@@ -0,0 +1,348 @@
+use crate::{
+    block_time::BlockTimeCache,
+    channel::Channel,
+    config::{Config, Role},
+    debounce::{self, DebounceTx},
+    deadletter::DeadLetters,
+    finality::FinalityTracker,
+    leaderboard::Leaderboard,
+    listener::{ParseLimiter, Resubscribe, SubscriptionSlots},
+    metrics::{Metrics, ProgramCounters},
+    orders::{now_micros, OrderStates},
+    otlp,
+    pause::{Held, Pause},
+    reorder::{self, ReorderTx},
+    replay::ReplayBuffer,
+    rollback::{self, RollbackWatch},
+    sla::{self, SlaMonitor},
+    snapshot,
+    tick::{self, Ticker},
+    tx_index::TxIndexCache,
+    EventRecord,
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::watch;
+
+/// Default for `CHANNEL_CAPACITY`.
+pub const CHANNEL_CAPACITY: usize = 100;
+/// Broadcast channel capacity under `LOW_MEMORY`.
+pub const LOW_MEMORY_CHANNEL_CAPACITY: usize = 16;
+
+//
+// ---------------- Shared state
+//
+#[derive(Clone)]
+pub struct AppState {
+    pub channel: Channel,
+    /// One extra channel per watched program, behind `/ws/:program_id`.
+    pub program_channels: Arc<HashMap<Pubkey, Channel>>,
+    pub replay: Arc<ReplayBuffer>,
+    pub dead_letters: Arc<DeadLetters>,
+    pub orders: Arc<OrderStates>,
+    pub leaderboard: Arc<Leaderboard>,
+    /// Activity of the current `Tick` window.
+    pub ticker: Arc<Ticker>,
+    /// Set when `SLA_MIN_COMPLETIONS_PER_MIN` is.
+    pub sla: Option<Arc<SlaMonitor>>,
+    /// Set when `ROLLBACK_DETECTION` is on.
+    pub rollback: Option<Arc<RollbackWatch>>,
+    pub metrics: Arc<Metrics>,
+    /// Per-program decode counters, in `PROGRAM_ID` order.
+    pub program_stats: Arc<Vec<(Pubkey, ProgramCounters)>>,
+    pub rpc: Arc<RpcClient>,
+    pub block_times: Option<Arc<BlockTimeCache>>,
+    /// Set when `ORDERING_METADATA` is on.
+    pub tx_indexes: Option<Arc<TxIndexCache>>,
+    /// Set when `REORDER_HOLD_MS` is configured.
+    pub reorder: Option<ReorderTx>,
+    /// Set when `BROADCAST_DEBOUNCE_MS` is configured.
+    pub debounce: Option<DebounceTx>,
+    pub config: Arc<Config>,
+    /// Number of live PubSub subscriptions; `/readyz` wants one per program.
+    pub live_subscriptions: Arc<AtomicUsize>,
+    /// `MAX_SUBSCRIPTIONS` accounting.
+    pub subscription_slots: Arc<SubscriptionSlots>,
+    /// Signals every listener for `POST /admin/resubscribe`.
+    pub resubscribe: Arc<Resubscribe>,
+    /// Set when `DUAL_COMMITMENT` is on.
+    pub finality: Option<Arc<FinalityTracker>>,
+    /// Set when `MAX_PARSE_RATE` is.
+    pub parse_limiter: Option<Arc<ParseLimiter>>,
+    /// Broadcasts withheld by `POST /admin/pause`.
+    pub pause: Arc<Pause>,
+    /// Set while `ROLE=standby` and not yet promoted.
+    pub standby: Arc<AtomicBool>,
+    /// Set by `shutdown::exit`; open sockets close with `1001` on it.
+    pub shutting_down: Arc<watch::Sender<bool>>,
+}
+
+impl AppState {
+    /// Builds the event pipeline: channels, buffers and projections. Needs a
+    /// Tokio runtime, as some parts run as background tasks.
+    pub fn new(config: &Config) -> Self {
+        let new_channel = |program_id| {
+            Channel::new(
+                config.channel_capacity(program_id),
+                config.lag_policy,
+                config.client_queue_size,
+            )
+        };
+        let program_channels = config
+            .program_ids
+            .iter()
+            .map(|id| (*id, new_channel(Some(id))))
+            .collect();
+        let (reorder_tx, reorder_rx) = std::sync::mpsc::channel();
+        let (debounce_tx, debounce_rx) = std::sync::mpsc::channel();
+        let rpc = Arc::new(RpcClient::new(config.rpc_url.clone()));
+        let state = Self {
+            channel: new_channel(None),
+            program_channels: Arc::new(program_channels),
+            replay: Arc::new(ReplayBuffer::new(config.replay_buffer_size)),
+            dead_letters: Arc::new(DeadLetters::new(config.dead_letter_buffer_size)),
+            orders: Arc::new(OrderStates::new(config.order_state_capacity)),
+            leaderboard: Arc::new(Leaderboard::default()),
+            ticker: Arc::new(Ticker::default()),
+            sla: config
+                .sla_min_rate
+                .map(|_| Arc::new(SlaMonitor::new(config.sla_window))),
+            rollback: config
+                .rollback_detection
+                .then(|| Arc::new(RollbackWatch::default())),
+            metrics: Arc::new(Metrics::default()),
+            program_stats: Arc::new(
+                config
+                    .program_ids
+                    .iter()
+                    .map(|id| (*id, ProgramCounters::default()))
+                    .collect(),
+            ),
+            block_times: config.enrich_block_time.then(|| {
+                Arc::new(BlockTimeCache::new(
+                    rpc.clone(),
+                    config.block_time_cache_size,
+                ))
+            }),
+            tx_indexes: config.ordering_metadata.then(|| {
+                Arc::new(TxIndexCache::new(rpc.clone(), config.tx_index_cache_size))
+            }),
+            rpc,
+            reorder: config.reorder_hold.map(|_| reorder_tx),
+            debounce: config.broadcast_debounce.map(|_| debounce_tx),
+            config: Arc::new(config.clone()),
+            live_subscriptions: Arc::new(AtomicUsize::new(0)),
+            resubscribe: Arc::new(Resubscribe::default()),
+            subscription_slots: Arc::new(SubscriptionSlots::new(config.max_subscriptions)),
+            finality: config
+                .dual_commitment
+                .then(|| Arc::new(FinalityTracker::new(config.dual_commitment_policy))),
+            parse_limiter: config.max_parse_rate.map(|rate| Arc::new(ParseLimiter::new(rate))),
+            pause: Arc::new(Pause::new(config.pause_buffer_size)),
+            standby: Arc::new(AtomicBool::new(config.role == Role::Standby)),
+            shutting_down: Arc::new(watch::Sender::new(false)),
+        };
+
+        if let Some(path) = &config.snapshot_path {
+            snapshot::restore(path, &state.orders, &state.leaderboard);
+            snapshot::spawn(
+                path,
+                config.snapshot_interval,
+                state.orders.clone(),
+                state.leaderboard.clone(),
+            );
+        }
+
+        if let Some(hold) = config.reorder_hold {
+            reorder::spawn(state.clone(), reorder_rx, hold);
+        }
+        if let Some(quiet) = config.broadcast_debounce {
+            debounce::spawn(state.clone(), debounce_rx, quiet);
+        }
+        if let Some(interval) = config.tick_interval {
+            tick::spawn(state.clone(), interval);
+        }
+        if let Some(threshold) = config.sla_min_rate {
+            sla::spawn(state.clone(), threshold, config.sla_hold);
+        }
+        if config.rollback_detection {
+            rollback::spawn(state.clone(), config.rollback_poll_interval);
+        }
+        if let Some(endpoint) = &config.otlp_endpoint {
+            otlp::spawn(state.clone(), endpoint, config.otlp_interval);
+        }
+
+        state
+    }
+
+    /// Entry point for chain-sourced events; goes through the reordering
+    /// buffer when enabled.
+    pub fn emit(&self, program_id: &Pubkey, mut record: EventRecord) {
+        self.stamp(&mut record);
+        match &self.reorder {
+            Some(reorder) => {
+                let _ = reorder.send((*program_id, record));
+            }
+            None => self.publish(Some(program_id), record),
+        }
+    }
+
+    /// Records the event (in the projections unless `LOW_MEMORY`), then
+    /// releases it for broadcast, debounced when enabled. With `TICK_ONLY`
+    /// only the service's own events are released.
+    pub fn publish(&self, program_id: Option<&Pubkey>, mut record: EventRecord) {
+        self.stamp(&mut record);
+        let counted = !self.is_repeat(program_id, &record);
+        if counted && !self.config.low_memory {
+            if self.orders.apply(&record) {
+                Metrics::inc(&self.metrics.order_states_evicted_total);
+            }
+            self.leaderboard.apply(&record);
+        }
+        if counted {
+            self.ticker.apply(&record);
+        }
+        if counted && let Some(sla) = &self.sla {
+            sla.apply(&record);
+        }
+        if let Some(rollback) = &self.rollback {
+            rollback.apply(program_id, &record);
+        }
+        let own = record.event.is_own();
+        let record = self.replay.push(program_id, record);
+        if self.config.tick_only && !own {
+            return;
+        }
+        let item = (program_id.copied(), record);
+        match &self.debounce {
+            Some(debounce) if !own => {
+                let _ = debounce.send(item);
+            }
+            _ => self.release(item),
+        }
+    }
+
+    /// Sets `received_at` with `RECEIVED_AT`, unless an earlier stage did.
+    fn stamp(&self, record: &mut EventRecord) {
+        if self.config.received_at && record.received_at.is_none() {
+            record.received_at = Some(now_micros());
+        }
+    }
+
+    /// Broadcasts the event unless broadcasting is paused. A standby drops
+    /// it: its buffers already have it, and nobody is connected.
+    pub fn release(&self, item: Held) {
+        if self.is_standby() {
+            return;
+        }
+        if self.pause.send_or_hold(item, |item| self.broadcast(item)) {
+            Metrics::inc(&self.metrics.paused_events_dropped_total);
+        }
+    }
+
+    pub fn program_counters(&self, program_id: &Pubkey) -> Option<&ProgramCounters> {
+        self.program_stats
+            .iter()
+            .find(|(id, _)| id == program_id)
+            .map(|(_, counters)| counters)
+    }
+
+    /// Whether `record` is a second copy of an event at a higher commitment
+    /// (`DUAL_COMMITMENT` with `emit_both`), which is still broadcast but
+    /// must not be counted again.
+    fn is_repeat(&self, program_id: Option<&Pubkey>, record: &EventRecord) -> bool {
+        let (Some(tracker), Some(program_id), Some(signature), Some(finality)) =
+            (&self.finality, program_id, &record.signature, record.finality)
+        else {
+            return false;
+        };
+        tracker.is_repeat(program_id, signature, record.event.kind(), finality)
+    }
+
+    /// Whether this is an unpromoted `ROLE=standby` instance.
+    pub fn is_standby(&self) -> bool {
+        self.standby.load(Ordering::Relaxed)
+    }
+
+    /// Starts serving as primary; false when it already was.
+    pub fn promote(&self) -> bool {
+        self.standby.swap(false, Ordering::Relaxed)
+    }
+
+    /// Oldest unconsumed event over all channels, for `broadcast_oldest_lag_seconds`.
+    pub fn broadcast_lag(&self) -> Duration {
+        self.program_channels
+            .values()
+            .chain([&self.channel])
+            .filter_map(Channel::oldest_unconsumed)
+            .max()
+            .unwrap_or_default()
+    }
+
+    pub fn broadcast(&self, (program_id, record): Held) {
+        if let Some(channel) = program_id.and_then(|id| self.program_channels.get(&id)) {
+            channel.send(record.clone());
+        }
+        self.channel.send(record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{events::EventKind, finality::Finality, WebEvent};
+
+    const PROGRAM: &str = "AdScDF7jTLCmb3iP4ZPugb6kxDtix1U7pVRu99VDJwdy";
+
+    /// Publishes `OrderCompleted` of transaction "sig" at each level in
+    /// turn, as the listener does under `DUAL_COMMITMENT`.
+    fn publish_both_copies(state: &AppState) {
+        let program_id: Pubkey = PROGRAM.parse().unwrap();
+        for finality in [Finality::Confirmed, Finality::Finalized] {
+            let tracker = state.finality.as_ref().unwrap();
+            assert!(tracker.admit(&program_id, "sig", EventKind::OrderCompleted, finality));
+            state.publish(
+                Some(&program_id),
+                EventRecord {
+                    slot: Some(1200),
+                    signature: Some("sig".to_string()),
+                    finality: Some(finality),
+                    ..EventRecord::test(WebEvent::OrderCompleted {
+                        order: "o1".to_string(),
+                        order_id: 1,
+                        courier: "k1".to_string(),
+                        amount: 100,
+                    })
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn dual_commitment_copies_count_once() {
+        let config = Config::from_pairs(&[
+            ("PROGRAM_ID", PROGRAM),
+            ("DUAL_COMMITMENT", "true"),
+            ("SLA_MIN_COMPLETIONS_PER_MIN", "1"),
+            ("SLA_WINDOW_SECS", "60"),
+        ]);
+        let state = AppState::new(&config);
+        publish_both_copies(&state);
+
+        let top = state.leaderboard.top(10);
+        assert_eq!((top.len(), top[0].completed, top[0].volume), (1, 1, 100));
+        let window = state.ticker.take();
+        assert_eq!((window.completed, window.total_volume), (1, 100));
+        assert_eq!(state.sla.as_ref().unwrap().rate(std::time::Instant::now()), 1.0);
+        // Both copies are still broadcast and buffered.
+        assert_eq!(state.replay.last_seq(), 2);
+    }
+}
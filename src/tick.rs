@@ -0,0 +1,76 @@
+use crate::{state::AppState, EventRecord, WebEvent};
+use std::{sync::Mutex, thread, time::Duration};
+
+//
+// ---------------- Periodic activity summary (`TICK_INTERVAL_SECS`)
+//
+// Counts published events and, every interval, publishes a `WebEvent::Tick`
+// summarizing the window that just ended, then starts a new one.
+#[derive(Default)]
+pub struct Ticker {
+    window: Mutex<Window>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Window {
+    created: u64,
+    accepted: u64,
+    pub(crate) completed: u64,
+    pub(crate) total_volume: u64,
+}
+
+impl Ticker {
+    pub fn apply(&self, record: &EventRecord) {
+        let mut window = self.window.lock().unwrap();
+        match &record.event {
+            WebEvent::OrderCreated { .. } => window.created += 1,
+            WebEvent::OrderAccepted { .. } => window.accepted += 1,
+            WebEvent::OrderCompleted { amount, .. } => {
+                window.completed += 1;
+                window.total_volume = window.total_volume.saturating_add(*amount);
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn take(&self) -> Window {
+        std::mem::take(&mut *self.window.lock().unwrap())
+    }
+
+    /// Drops the open window, so the next `Tick` only counts what comes after.
+    pub fn reset(&self) {
+        self.take();
+    }
+}
+
+pub fn spawn(state: AppState, interval: Duration) {
+    thread::spawn(move || {
+        loop {
+            thread::sleep(interval);
+            let window = state.ticker.take();
+            state.publish(
+                None,
+                EventRecord {
+                    event: WebEvent::Tick {
+                        window_secs: interval.as_secs(),
+                        created: window.created,
+                        accepted: window.accepted,
+                        completed: window.completed,
+                        total_volume: window.total_volume,
+                    },
+                    slot: None,
+                    signature: None,
+                    block_time: None,
+                    tx_index: None,
+                    log_index: None,
+                    pda_valid: None,
+                    finality: None,
+                    raw_base64: None,
+                    received_at: None,
+                    source: None,
+                    seq: 0,
+                },
+            );
+        }
+    });
+}
@@ -0,0 +1,71 @@
+use crate::metrics::Metrics;
+use lru::LruCache;
+use solana_client::{rpc_client::RpcClient, rpc_config::RpcBlockConfig};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_transaction_status::TransactionDetails;
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
+
+//
+// ---------------- Transaction index lookup (`ORDERING_METADATA`)
+//
+// Log notifications don't say where in its block a transaction is, so the
+// block's signature list is fetched once per slot and kept in an LRU, like
+// block times. The position in that list is the transaction's index, the
+// same on every node. Failed lookups are not cached.
+pub struct TxIndexCache {
+    rpc: Arc<RpcClient>,
+    cache: Mutex<LruCache<u64, Arc<HashMap<String, u32>>>>,
+}
+
+impl TxIndexCache {
+    pub fn new(rpc: Arc<RpcClient>, size: usize) -> Self {
+        let size = NonZeroUsize::new(size).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            rpc,
+            cache: Mutex::new(LruCache::new(size)),
+        }
+    }
+
+    pub fn get(&self, slot: u64, signature: &str, metrics: &Metrics) -> Option<u32> {
+        let cached = self.cache.lock().unwrap().get(&slot).cloned();
+        let indexes = match cached {
+            Some(indexes) => indexes,
+            None => {
+                let Some(indexes) = self.fetch(slot) else {
+                    Metrics::inc(&metrics.tx_index_lookup_failures_total);
+                    return None;
+                };
+                self.cache.lock().unwrap().put(slot, indexes.clone());
+                indexes
+            }
+        };
+        indexes.get(signature).copied()
+    }
+
+    fn fetch(&self, slot: u64) -> Option<Arc<HashMap<String, u32>>> {
+        let block = self
+            .rpc
+            .get_block_with_config(
+                slot,
+                RpcBlockConfig {
+                    encoding: None,
+                    transaction_details: Some(TransactionDetails::Signatures),
+                    rewards: Some(false),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .ok()?;
+        let indexes = block
+            .signatures?
+            .into_iter()
+            .enumerate()
+            .map(|(i, signature)| (signature, i as u32))
+            .collect();
+        Some(Arc::new(indexes))
+    }
+}
@@ -0,0 +1,32 @@
+use std::process::Command;
+
+/// sysexits.h: configuration error.
+const EX_CONFIG: i32 = 78;
+
+#[test]
+fn missing_program_id_exits_with_ex_config() {
+    let output = Command::new(env!("CARGO_BIN_EXE_block_delivery_service"))
+        .env_clear()
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(EX_CONFIG));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr,
+        "config error: missing required configuration; set: PROGRAM_ID (program id(s) whose events are decoded, comma-separated)\n"
+    );
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn malformed_setting_exits_with_ex_config() {
+    let output = Command::new(env!("CARGO_BIN_EXE_block_delivery_service"))
+        .env_clear()
+        .env("PROGRAM_ID", "11111111111111111111111111111111")
+        .env("REPLAY_BUFFER_SIZE", "lots")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(EX_CONFIG));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr, "config error: invalid REPLAY_BUFFER_SIZE: \"lots\"\n");
+}